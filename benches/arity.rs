@@ -0,0 +1,94 @@
+use criterion::measurement::WallTime;
+use criterion::{
+    criterion_group, criterion_main, BatchSize, BenchmarkGroup, BenchmarkId, Criterion,
+};
+use gen_id_allocator::untyped::UntypedId;
+use gen_id_allocator::Id;
+use gen_id_priority_queue::IndexedMinQueue;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+const LEN: usize = 10_000;
+
+struct Arena;
+
+fn get_id(index: usize) -> Id<Arena> {
+    Id::new(UntypedId::first(index))
+}
+
+fn values() -> Vec<u32> {
+    let mut rng = StdRng::seed_from_u64(0);
+    (0..LEN).map(|_| rng.gen()).collect()
+}
+
+fn fill<const D: usize>(values: &[u32]) -> IndexedMinQueue<Arena, u32, D> {
+    let mut queue = IndexedMinQueue::default();
+    for (i, value) in values.iter().enumerate() {
+        queue.insert(get_id(i), *value);
+    }
+    queue
+}
+
+fn insert<const D: usize>(group: &mut BenchmarkGroup<WallTime>, values: &[u32]) {
+    group.bench_function(BenchmarkId::from_parameter(D), |b| {
+        b.iter(|| fill::<D>(values))
+    });
+}
+
+fn pop<const D: usize>(group: &mut BenchmarkGroup<WallTime>, values: &[u32]) {
+    let queue = fill::<D>(values);
+    group.bench_function(BenchmarkId::from_parameter(D), |b| {
+        b.iter_batched_ref(
+            || queue.clone(),
+            |queue| {
+                for _ in 0..LEN {
+                    queue.pop();
+                }
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn decrease<const D: usize>(group: &mut BenchmarkGroup<WallTime>, values: &[u32]) {
+    let queue = fill::<D>(values);
+    group.bench_function(BenchmarkId::from_parameter(D), |b| {
+        b.iter_batched_ref(
+            || queue.clone(),
+            |queue| {
+                for (i, value) in values.iter().enumerate() {
+                    queue.decrease(get_id(i), value / 2);
+                }
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn arity(c: &mut Criterion) {
+    let values = values();
+
+    let mut group = c.benchmark_group("insert");
+    insert::<2>(&mut group, &values);
+    insert::<4>(&mut group, &values);
+    insert::<8>(&mut group, &values);
+    insert::<16>(&mut group, &values);
+    group.finish();
+
+    let mut group = c.benchmark_group("pop");
+    pop::<2>(&mut group, &values);
+    pop::<4>(&mut group, &values);
+    pop::<8>(&mut group, &values);
+    pop::<16>(&mut group, &values);
+    group.finish();
+
+    let mut group = c.benchmark_group("decrease");
+    decrease::<2>(&mut group, &values);
+    decrease::<4>(&mut group, &values);
+    decrease::<8>(&mut group, &values);
+    decrease::<16>(&mut group, &values);
+    group.finish();
+}
+
+criterion_group!(benches, arity);
+criterion_main!(benches);
@@ -0,0 +1,52 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use gen_id_allocator::untyped::UntypedId;
+use gen_id_allocator::Id;
+use gen_id_priority_queue::IndexedMinQueue;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+const LENS: [usize; 3] = [1_000, 10_000, 100_000];
+
+struct Arena;
+
+fn get_id(index: usize) -> Id<Arena> {
+    Id::new(UntypedId::first(index))
+}
+
+fn values(len: usize) -> Vec<u32> {
+    let mut rng = StdRng::seed_from_u64(0);
+    (0..len).map(|_| rng.gen()).collect()
+}
+
+fn build(c: &mut Criterion) {
+    let mut group = c.benchmark_group("build");
+
+    for len in LENS.iter().copied() {
+        let values = values(len);
+
+        group.bench_function(BenchmarkId::new("insert", len), |b| {
+            b.iter(|| {
+                let mut queue = IndexedMinQueue::<Arena, u32>::default();
+                for (i, value) in values.iter().enumerate() {
+                    queue.insert(get_id(i), *value);
+                }
+                queue
+            })
+        });
+
+        group.bench_function(BenchmarkId::new("from_iter", len), |b| {
+            b.iter(|| {
+                values
+                    .iter()
+                    .enumerate()
+                    .map(|(i, value)| (get_id(i), *value))
+                    .collect::<IndexedMinQueue<Arena, u32>>()
+            })
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, build);
+criterion_main!(benches);
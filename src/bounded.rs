@@ -0,0 +1,471 @@
+use crate::compare::{Compare, Min};
+use crate::error::QueueError;
+use crate::invariant::{self, InvariantError};
+use crate::minmax::IndexedMinMaxQueue;
+use crate::storage::Heap;
+use crate::update::Update;
+use gen_id_allocator::{Id, ValidId};
+use std::cmp::Ordering;
+use std::ops::Index;
+
+/// The outcome of inserting into a [`BoundedIndexedQueue`].
+#[derive(Debug)]
+pub enum Insertion<Arena, T> {
+    /// The entry was added or updated without displacing another.
+    Inserted,
+    /// The queue was full, so the worst entry was removed to make room.
+    Evicted(Id<Arena>, T),
+    /// The queue was full and the new entry was not ordered before the worst entry.
+    Rejected(Id<Arena>, T),
+}
+
+impl<Arena, T: Clone> Clone for Insertion<Arena, T> {
+    #[inline]
+    fn clone(&self) -> Self {
+        match self {
+            Insertion::Inserted => Insertion::Inserted,
+            Insertion::Evicted(id, value) => Insertion::Evicted(*id, value.clone()),
+            Insertion::Rejected(id, value) => Insertion::Rejected(*id, value.clone()),
+        }
+    }
+}
+
+impl<Arena, T: PartialEq> PartialEq for Insertion<Arena, T> {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Insertion::Inserted, Insertion::Inserted) => true,
+            (Insertion::Evicted(a, x), Insertion::Evicted(b, y)) => a == b && x == y,
+            (Insertion::Rejected(a, x), Insertion::Rejected(b, y)) => a == b && x == y,
+            _ => false,
+        }
+    }
+}
+
+impl<Arena, T: Eq> Eq for Insertion<Arena, T> {}
+
+/// An Id-indexed priority queue that keeps at most `capacity` entries, ordered by the comparator `C`.
+///
+/// Once full, a new entry displaces the entry at the back of the queue only if it is ordered before it.
+#[derive(Debug)]
+pub struct BoundedIndexedQueue<Arena, T, C = Min> {
+    inner: IndexedMinMaxQueue<Arena, T, C>,
+    capacity: usize,
+}
+
+impl<Arena, T: Clone, C: Clone> Clone for BoundedIndexedQueue<Arena, T, C> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            capacity: self.capacity,
+        }
+    }
+
+    #[inline]
+    fn clone_from(&mut self, rhs: &Self) {
+        self.inner.clone_from(&rhs.inner);
+        self.capacity = rhs.capacity;
+    }
+}
+
+impl<Arena, T, C: Default> BoundedIndexedQueue<Arena, T, C> {
+    #[inline]
+    pub fn new(capacity: usize) -> Self {
+        Self::with_comparator(capacity, C::default())
+    }
+}
+
+impl<Arena, T, C> BoundedIndexedQueue<Arena, T, C> {
+    #[inline]
+    pub fn with_comparator(capacity: usize, compare: C) -> Self {
+        Self {
+            inner: IndexedMinMaxQueue::with_comparator(compare),
+            capacity,
+        }
+    }
+
+    #[inline]
+    pub fn comparator(&self) -> &C {
+        self.inner.comparator()
+    }
+
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+impl<Arena, T, C: Compare<T>> BoundedIndexedQueue<Arena, T, C> {
+    #[inline]
+    pub fn clear(&mut self) {
+        self.inner.clear();
+        self.check_invariants();
+    }
+
+    /// Inserts or updates the entry for `id`. A new id on a full queue either evicts the worst
+    /// entry or is rejected, unless it replaces the entry of a dead id in the same slot.
+    #[inline]
+    pub fn insert(&mut self, id: impl ValidId<Arena = Arena>, value: T) -> Insertion<Arena, T> {
+        let id = id.id();
+
+        // A stale entry in the slot is dropped by the inner insert, which leaves room without evicting
+        if self.inner.get(id).is_some() || self.inner.has_stale_entry(id) || !self.is_full() {
+            self.inner.insert(id, value);
+            self.check_invariants();
+            return Insertion::Inserted;
+        }
+
+        let is_better = match self.inner.peek_max() {
+            Some((_, worst)) => self.comparator().compare(&value, worst) == Ordering::Less,
+            None => false,
+        };
+
+        if !is_better {
+            return Insertion::Rejected(id, value);
+        }
+
+        match self.inner.pop_max() {
+            Some((worst_id, worst)) => {
+                self.inner.insert(id, value);
+                self.check_invariants();
+                Insertion::Evicted(worst_id, worst)
+            }
+            None => Insertion::Rejected(id, value),
+        }
+    }
+
+    /// As in [`insert`](Self::insert), failing with [`QueueError::CapacityOverflow`] only if the
+    /// queue is not full and cannot address another entry.
+    #[inline]
+    pub fn try_insert(
+        &mut self,
+        id: impl ValidId<Arena = Arena>,
+        value: T,
+    ) -> Result<Insertion<Arena, T>, QueueError> {
+        let id = id.id();
+
+        if !self.is_full() && !self.inner.can_insert(id.untyped) {
+            return Err(QueueError::CapacityOverflow);
+        }
+
+        Ok(self.insert(id, value))
+    }
+
+    #[inline]
+    pub fn remove(&mut self, id: impl ValidId<Arena = Arena>) -> Option<(Id<Arena>, T)> {
+        let removed = self.inner.remove(id);
+        self.check_invariants();
+        removed
+    }
+
+    #[inline]
+    pub fn get(&self, id: impl ValidId<Arena = Arena>) -> Option<&T> {
+        self.inner.get(id)
+    }
+
+    /// Returns the best entry.
+    #[inline]
+    pub fn peek(&self) -> Option<(Id<Arena>, &T)> {
+        self.inner.peek_min()
+    }
+
+    /// Returns the worst entry, which is the next to be evicted.
+    #[inline]
+    pub fn peek_worst(&self) -> Option<(Id<Arena>, &T)> {
+        self.inner.peek_max()
+    }
+
+    /// As in [`IndexedMinMaxQueue::remove_position`].
+    #[inline]
+    pub fn remove_position(&mut self, position: usize) -> Option<(Id<Arena>, T)> {
+        let removed = self.inner.remove_position(position);
+        self.check_invariants();
+        removed
+    }
+
+    /// As in [`IndexedMinMaxQueue::try_remove_position`].
+    #[inline]
+    pub fn try_remove_position(&mut self, position: usize) -> Result<(Id<Arena>, T), QueueError> {
+        let removed = self.inner.try_remove_position(position);
+        self.check_invariants();
+        removed
+    }
+
+    #[inline]
+    pub fn pop(&mut self) -> Option<(Id<Arena>, T)> {
+        let popped = self.inner.pop_min();
+        self.check_invariants();
+        popped
+    }
+
+    #[inline]
+    pub fn pop_worst(&mut self) -> Option<(Id<Arena>, T)> {
+        let popped = self.inner.pop_max();
+        self.check_invariants();
+        popped
+    }
+
+    /// Replaces the value for `id` if `value` is smaller than it.
+    #[inline]
+    pub fn decrease(&mut self, id: impl ValidId<Arena = Arena>, value: T) -> Update<T>
+    where
+        T: Ord,
+    {
+        let update = self.inner.decrease(id, value);
+        self.check_invariants();
+        update
+    }
+
+    /// As in [`IndexedMinMaxQueue::try_decrease`].
+    #[inline]
+    pub fn try_decrease(
+        &mut self,
+        id: impl ValidId<Arena = Arena>,
+        value: T,
+    ) -> Result<T, QueueError>
+    where
+        T: Ord,
+    {
+        self.decrease(id, value).into_result()
+    }
+
+    /// Replaces the value for `id` if `value` is larger than it.
+    #[inline]
+    pub fn increase(&mut self, id: impl ValidId<Arena = Arena>, value: T) -> Update<T>
+    where
+        T: Ord,
+    {
+        let update = self.inner.increase(id, value);
+        self.check_invariants();
+        update
+    }
+
+    /// Moves the entry for `id` towards the front of the queue if `value` is ordered before its current value.
+    #[inline]
+    pub fn promote(&mut self, id: impl ValidId<Arena = Arena>, value: T) -> Update<T> {
+        let update = self.inner.promote(id, value);
+        self.check_invariants();
+        update
+    }
+
+    /// Moves the entry for `id` towards the back of the queue if `value` is ordered after its current value.
+    #[inline]
+    pub fn demote(&mut self, id: impl ValidId<Arena = Arena>, value: T) -> Update<T> {
+        let update = self.inner.demote(id, value);
+        self.check_invariants();
+        update
+    }
+
+    /// As in [`IndexedMinMaxQueue::iter_unordered`].
+    #[inline]
+    pub fn iter_unordered(&self) -> impl Iterator<Item = (Id<Arena>, &T)> {
+        self.inner.iter_unordered()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.len() >= self.capacity
+    }
+
+    /// Checks the invariants of the underlying min-max queue and that it holds no more than `capacity` entries.
+    #[inline]
+    pub fn validate(&self) -> Result<(), InvariantError> {
+        self.inner.validate()?;
+
+        if self.len() > self.capacity {
+            return Err(InvariantError::OverCapacity {
+                len: self.len(),
+                capacity: self.capacity,
+            });
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    fn check_invariants(&self) {
+        invariant::debug_check(|| self.validate());
+    }
+}
+
+impl<Arena, T, C, V: ValidId<Arena = Arena>> Index<V> for BoundedIndexedQueue<Arena, T, C> {
+    type Output = Option<T>;
+
+    #[inline]
+    fn index(&self, index: V) -> &Self::Output {
+        self.inner.index(index)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::storage::CAPACITY;
+    use crate::test_util::{get_id, Arena};
+    use crate::Max;
+    use gen_id_allocator::Allocator;
+
+    #[test]
+    fn fills_to_capacity() {
+        let mut queue = BoundedIndexedQueue::<Arena, u32>::new(2);
+
+        assert_eq!(Insertion::Inserted, queue.insert(get_id(0), 5));
+        assert_eq!(Insertion::Inserted, queue.insert(get_id(1), 3));
+
+        assert!(queue.is_full());
+        assert_eq!(Some((get_id(1), &3)), queue.peek());
+        assert_eq!(Some((get_id(0), &5)), queue.peek_worst());
+    }
+
+    #[test]
+    fn better_entry_evicts_worst() {
+        let mut queue = BoundedIndexedQueue::<Arena, u32>::new(2);
+        queue.insert(get_id(0), 5);
+        queue.insert(get_id(1), 3);
+
+        assert_eq!(Insertion::Evicted(get_id(0), 5), queue.insert(get_id(2), 4));
+        assert_eq!(2, queue.len());
+        assert_eq!(None, queue[get_id(0)]);
+        assert_eq!(Some((get_id(2), &4)), queue.peek_worst());
+    }
+
+    #[test]
+    fn worse_or_equal_entry_is_rejected() {
+        let mut queue = BoundedIndexedQueue::<Arena, u32>::new(2);
+        queue.insert(get_id(0), 5);
+        queue.insert(get_id(1), 3);
+
+        assert_eq!(
+            Insertion::Rejected(get_id(2), 6),
+            queue.insert(get_id(2), 6)
+        );
+        assert_eq!(
+            Insertion::Rejected(get_id(2), 5),
+            queue.insert(get_id(2), 5)
+        );
+        assert_eq!(None, queue.get(get_id(2)));
+    }
+
+    #[test]
+    fn updating_an_entry_on_a_full_queue_does_not_evict() {
+        let mut queue = BoundedIndexedQueue::<Arena, u32>::new(2);
+        queue.insert(get_id(0), 5);
+        queue.insert(get_id(1), 3);
+
+        assert_eq!(Insertion::Inserted, queue.insert(get_id(0), 1));
+        assert_eq!(Some((get_id(0), &1)), queue.peek());
+        assert_eq!(2, queue.len());
+    }
+
+    #[test]
+    fn reused_slot_replaces_its_stale_entry_on_a_full_queue() {
+        let mut alloc = Allocator::<Arena>::default();
+        let first = alloc.create();
+        let dead = alloc.create();
+
+        let mut queue = BoundedIndexedQueue::<Arena, u32>::new(2);
+        queue.insert(first, 1);
+        queue.insert(dead, 2);
+
+        alloc.kill(dead);
+        let reused = alloc.create();
+        assert_eq!(dead.untyped.index(), reused.untyped.index());
+
+        assert_eq!(Insertion::Inserted, queue.insert(reused, 5));
+        assert_eq!(2, queue.len());
+        assert_eq!(Some(&1), queue.get(first));
+        assert_eq!(Some(&5), queue.get(reused));
+        assert_eq!(None, queue.get(dead));
+        assert_eq!(Ok(()), queue.validate());
+    }
+
+    #[test]
+    fn zero_capacity_rejects_everything() {
+        let mut queue = BoundedIndexedQueue::<Arena, u32>::new(0);
+
+        assert_eq!(
+            Insertion::Rejected(get_id(0), 1),
+            queue.insert(get_id(0), 1)
+        );
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn validate_detects_over_capacity() {
+        let mut queue = BoundedIndexedQueue::<Arena, u32>::new(2);
+        queue.insert(get_id(0), 5);
+        queue.insert(get_id(1), 5);
+        assert_eq!(Ok(()), queue.validate());
+
+        queue.capacity = 1;
+
+        assert_eq!(
+            Err(InvariantError::OverCapacity {
+                len: 2,
+                capacity: 1
+            }),
+            queue.validate()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "debug_invariants")]
+    #[should_panic(expected = "capacity is 1")]
+    fn mutations_check_capacity() {
+        let mut queue = BoundedIndexedQueue::<Arena, u32>::new(2);
+        queue.insert(get_id(0), 5);
+        queue.insert(get_id(1), 5);
+
+        queue.capacity = 1;
+
+        queue.decrease(get_id(0), 4);
+    }
+
+    #[test]
+    fn max_comparator_keeps_largest() {
+        let mut queue = BoundedIndexedQueue::<Arena, u32, Max>::new(2);
+        queue.insert(get_id(0), 5);
+        queue.insert(get_id(1), 3);
+
+        assert_eq!(Insertion::Evicted(get_id(1), 3), queue.insert(get_id(2), 4));
+        assert_eq!(Some((get_id(0), 5)), queue.pop());
+        assert_eq!(Some((get_id(2), 4)), queue.pop_worst());
+    }
+
+    #[test]
+    fn try_insert_evicts_instead_of_overflowing() {
+        let mut queue = BoundedIndexedQueue::<Arena, u32>::new(1);
+        assert_eq!(Ok(Insertion::Inserted), queue.try_insert(get_id(0), 5));
+        assert_eq!(
+            Ok(Insertion::Rejected(get_id(1), 6)),
+            queue.try_insert(get_id(1), 6)
+        );
+        assert_eq!(
+            Ok(Insertion::Evicted(get_id(0), 5)),
+            queue.try_insert(get_id(2), 4)
+        );
+
+        let capacity = CAPACITY as usize;
+        let mut queue = BoundedIndexedQueue::<Arena, u32>::new(usize::MAX);
+        for i in 0..capacity {
+            queue.insert(get_id(i), 1);
+        }
+
+        assert_eq!(
+            Err(QueueError::CapacityOverflow),
+            queue.try_insert(get_id(capacity), 0)
+        );
+        assert_eq!(Ok(Insertion::Inserted), queue.try_insert(get_id(0), 0));
+    }
+}
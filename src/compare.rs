@@ -0,0 +1,54 @@
+use std::cmp::Ordering;
+
+/// Determines the order of the values in a queue.
+///
+/// Values that compare as `Less` are closer to the front of the queue and are popped first.
+pub trait Compare<T: ?Sized> {
+    fn compare(&self, a: &T, b: &T) -> Ordering;
+}
+
+/// Orders values from smallest to largest.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Min;
+
+impl<T: Ord + ?Sized> Compare<T> for Min {
+    #[inline]
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        a.cmp(b)
+    }
+}
+
+/// Orders values from largest to smallest.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Max;
+
+impl<T: Ord + ?Sized> Compare<T> for Max {
+    #[inline]
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        b.cmp(a)
+    }
+}
+
+impl<T: ?Sized, F: Fn(&T, &T) -> Ordering> Compare<T> for F {
+    #[inline]
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        self(a, b)
+    }
+}
+
+/// Orders values from smallest to largest by the key returned from a function.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct ByKey<F>(F);
+
+/// Orders values from smallest to largest by the key returned from `f`.
+#[inline]
+pub fn by_key<T: ?Sized, K: Ord, F: Fn(&T) -> K>(f: F) -> ByKey<F> {
+    ByKey(f)
+}
+
+impl<T: ?Sized, K: Ord, F: Fn(&T) -> K> Compare<T> for ByKey<F> {
+    #[inline]
+    fn compare(&self, a: &T, b: &T) -> Ordering {
+        (self.0)(a).cmp(&(self.0)(b))
+    }
+}
@@ -0,0 +1,257 @@
+use crate::compare::Compare;
+use crate::untyped::UntypedIndexedQueue;
+use gen_id_allocator::untyped::UntypedId;
+use gen_id_allocator::Id;
+use std::marker::PhantomData;
+
+/// A view into a single id of a queue, which may or may not have an entry.
+///
+/// Returned by `IndexedQueue::entry`. The position of the id is looked up once, and each
+/// operation restores the heap order with a single pass.
+#[derive(Debug)]
+pub enum Entry<'a, Arena, T, C, const D: usize> {
+    Occupied(OccupiedEntry<'a, Arena, T, C, D>),
+    Vacant(VacantEntry<'a, Arena, T, C, D>),
+}
+
+impl<'a, Arena, T, C: Compare<T>, const D: usize> Entry<'a, Arena, T, C, D> {
+    #[inline]
+    pub(crate) fn new(queue: &'a mut UntypedIndexedQueue<T, C, D>, id: UntypedId) -> Self {
+        match queue.position(id) {
+            Some(position) => Entry::Occupied(OccupiedEntry {
+                queue,
+                id,
+                position,
+                arena: PhantomData,
+            }),
+            None => Entry::Vacant(VacantEntry {
+                queue,
+                id,
+                arena: PhantomData,
+            }),
+        }
+    }
+
+    #[inline]
+    pub fn id(&self) -> Id<Arena> {
+        match self {
+            Entry::Occupied(entry) => entry.id(),
+            Entry::Vacant(entry) => entry.id(),
+        }
+    }
+}
+
+/// An entry for an id that is in the queue.
+#[derive(Debug)]
+pub struct OccupiedEntry<'a, Arena, T, C, const D: usize> {
+    queue: &'a mut UntypedIndexedQueue<T, C, D>,
+    id: UntypedId,
+    position: usize,
+    arena: PhantomData<Arena>,
+}
+
+impl<'a, Arena, T, C: Compare<T>, const D: usize> OccupiedEntry<'a, Arena, T, C, D> {
+    #[inline]
+    pub fn id(&self) -> Id<Arena> {
+        Id::new(self.id)
+    }
+
+    #[inline]
+    pub fn get(&self) -> &T {
+        self.queue.get_position(self.position).unwrap()
+    }
+
+    /// Replaces the value, returning the previous value.
+    #[inline]
+    pub fn insert(self, value: T) -> T {
+        self.queue.replace_at(self.position, value).unwrap()
+    }
+
+    /// Replaces the value if `value` is smaller than it, returning true if the value changed.
+    #[inline]
+    pub fn decrease_to(self, value: T) -> bool
+    where
+        T: Ord,
+    {
+        self.queue.decrease_at(self.position, value).is_updated()
+    }
+
+    /// Replaces the value if `value` is larger than it, returning true if the value changed.
+    #[inline]
+    pub fn increase_to(self, value: T) -> bool
+    where
+        T: Ord,
+    {
+        self.queue.increase_at(self.position, value).is_updated()
+    }
+
+    /// Replaces the value if `value` is ordered before it, returning true if the value changed.
+    #[inline]
+    pub fn promote_to(self, value: T) -> bool {
+        self.queue.promote_at(self.position, value).is_updated()
+    }
+
+    /// Replaces the value if `value` is ordered after it, returning true if the value changed.
+    #[inline]
+    pub fn demote_to(self, value: T) -> bool {
+        self.queue.demote_at(self.position, value).is_updated()
+    }
+
+    /// Modifies the value in place.
+    #[inline]
+    pub fn update_with(self, f: impl FnOnce(&mut T)) {
+        self.queue.update_at(self.position, f);
+    }
+
+    #[inline]
+    pub fn remove(self) -> T {
+        let (_id, value) = self.queue.remove_position(self.position).unwrap();
+        value
+    }
+}
+
+/// An entry for an id that is not in the queue.
+#[derive(Debug)]
+pub struct VacantEntry<'a, Arena, T, C, const D: usize> {
+    queue: &'a mut UntypedIndexedQueue<T, C, D>,
+    id: UntypedId,
+    arena: PhantomData<Arena>,
+}
+
+impl<'a, Arena, T, C: Compare<T>, const D: usize> VacantEntry<'a, Arena, T, C, D> {
+    #[inline]
+    pub fn id(&self) -> Id<Arena> {
+        Id::new(self.id)
+    }
+
+    #[inline]
+    pub fn insert(self, value: T) {
+        self.queue.push(self.id, value);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{get_id, Arena};
+    use crate::{IndexedMaxQueue, IndexedMinQueue};
+
+    fn new_queue() -> IndexedMinQueue<Arena, u32> {
+        let mut queue = IndexedMinQueue::default();
+        queue.insert(get_id(0), 2);
+        queue.insert(get_id(1), 4);
+        queue.insert(get_id(2), 6);
+        queue
+    }
+
+    fn sorted_ids(queue: &IndexedMinQueue<Arena, u32>) -> Vec<Id<Arena>> {
+        queue.iter_sorted().map(|(id, _)| id).collect()
+    }
+
+    #[test]
+    fn vacant_insert() {
+        let mut queue = new_queue();
+
+        match queue.entry(get_id(3)) {
+            Entry::Vacant(entry) => entry.insert(1),
+            Entry::Occupied(_) => panic!("expected vacant entry"),
+        }
+
+        assert_eq!(Some((get_id(3), &1)), queue.peek_id());
+        assert_eq!(4, queue.len());
+    }
+
+    #[test]
+    fn occupied_insert_returns_previous() {
+        let mut queue = new_queue();
+
+        match queue.entry(get_id(0)) {
+            Entry::Occupied(entry) => assert_eq!(2, entry.insert(5)),
+            Entry::Vacant(_) => panic!("expected occupied entry"),
+        }
+
+        assert_eq!(vec![get_id(1), get_id(0), get_id(2)], sorted_ids(&queue));
+    }
+
+    #[test]
+    fn decrease_to() {
+        let mut queue = new_queue();
+
+        if let Entry::Occupied(entry) = queue.entry(get_id(2)) {
+            assert!(!entry.decrease_to(7));
+        }
+        if let Entry::Occupied(entry) = queue.entry(get_id(2)) {
+            assert!(entry.decrease_to(1));
+        }
+
+        assert_eq!(vec![get_id(2), get_id(0), get_id(1)], sorted_ids(&queue));
+    }
+
+    #[test]
+    fn increase_to() {
+        let mut queue = new_queue();
+
+        if let Entry::Occupied(entry) = queue.entry(get_id(0)) {
+            assert!(!entry.increase_to(1));
+        }
+        if let Entry::Occupied(entry) = queue.entry(get_id(0)) {
+            assert!(entry.increase_to(5));
+        }
+
+        assert_eq!(vec![get_id(1), get_id(0), get_id(2)], sorted_ids(&queue));
+    }
+
+    #[test]
+    fn update_with() {
+        let mut queue = new_queue();
+
+        if let Entry::Occupied(entry) = queue.entry(get_id(0)) {
+            entry.update_with(|value| *value += 5);
+        }
+        if let Entry::Occupied(entry) = queue.entry(get_id(2)) {
+            entry.update_with(|value| *value -= 6);
+        }
+
+        assert_eq!(vec![get_id(2), get_id(1), get_id(0)], sorted_ids(&queue));
+    }
+
+    #[test]
+    fn remove() {
+        let mut queue = new_queue();
+
+        if let Entry::Occupied(entry) = queue.entry(get_id(1)) {
+            assert_eq!(get_id(1), entry.id());
+            assert_eq!(&4, entry.get());
+            assert_eq!(4, entry.remove());
+        }
+
+        assert_eq!(vec![get_id(0), get_id(2)], sorted_ids(&queue));
+        assert!(matches!(queue.entry(get_id(1)), Entry::Vacant(_)));
+    }
+
+    #[test]
+    fn max_queue_promote_to_moves_towards_front() {
+        let mut queue = IndexedMaxQueue::<Arena, u32>::default();
+        queue.insert(get_id(0), 2);
+        queue.insert(get_id(1), 4);
+
+        if let Entry::Occupied(entry) = queue.entry(get_id(0)) {
+            assert!(!entry.promote_to(1));
+        }
+        if let Entry::Occupied(entry) = queue.entry(get_id(0)) {
+            assert!(entry.promote_to(5));
+        }
+
+        assert_eq!(Some((get_id(0), &5)), queue.peek_id());
+    }
+
+    #[test]
+    fn max_queue_decrease_to_lowers_value() {
+        let mut queue = IndexedMaxQueue::<Arena, u32>::default();
+        queue.insert(get_id(0), 2);
+
+        if let Entry::Occupied(entry) = queue.entry(get_id(0)) {
+            assert!(entry.decrease_to(1));
+        }
+    }
+}
@@ -0,0 +1,34 @@
+use std::fmt::{Display, Formatter};
+
+/// The reasons a `try_*` queue operation can fail.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum QueueError {
+    /// The queue has no entries.
+    Empty,
+    /// The position is past the last entry of a non-empty queue.
+    OutOfBounds { position: usize, len: usize },
+    /// The id has no entry in the queue.
+    Absent,
+    /// The new value would not have moved the entry in the requested direction.
+    NotImproved,
+    /// The queue cannot address another position.
+    CapacityOverflow,
+}
+
+impl Display for QueueError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueueError::Empty => write!(f, "the queue is empty"),
+            QueueError::OutOfBounds { position, len } => write!(
+                f,
+                "position {} is out of bounds for a queue of length {}",
+                position, len
+            ),
+            QueueError::Absent => write!(f, "the id has no entry in the queue"),
+            QueueError::NotImproved => write!(f, "the new value does not improve the entry"),
+            QueueError::CapacityOverflow => write!(f, "the queue is at its maximum capacity"),
+        }
+    }
+}
+
+impl std::error::Error for QueueError {}
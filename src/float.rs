@@ -0,0 +1,239 @@
+use std::cmp::Ordering;
+use std::fmt::Debug;
+use std::marker::PhantomData;
+
+/// A floating point type that can be ordered by the IEEE 754 `totalOrder` predicate.
+pub trait Float: Copy + Debug {
+    fn total_cmp(&self, other: &Self) -> Ordering;
+
+    fn is_nan(self) -> bool;
+
+    fn abs(self) -> Self;
+}
+
+impl Float for f32 {
+    #[inline]
+    fn total_cmp(&self, other: &Self) -> Ordering {
+        f32::total_cmp(self, other)
+    }
+
+    #[inline]
+    fn is_nan(self) -> bool {
+        f32::is_nan(self)
+    }
+
+    #[inline]
+    fn abs(self) -> Self {
+        f32::abs(self)
+    }
+}
+
+impl Float for f64 {
+    #[inline]
+    fn total_cmp(&self, other: &Self) -> Ordering {
+        f64::total_cmp(self, other)
+    }
+
+    #[inline]
+    fn is_nan(self) -> bool {
+        f64::is_nan(self)
+    }
+
+    #[inline]
+    fn abs(self) -> Self {
+        f64::abs(self)
+    }
+}
+
+/// Determines how [`TotalOrd::new`] handles NaN.
+pub trait NanPolicy {
+    fn check<F: Float>(value: F) -> Option<F>;
+}
+
+/// NaN is rejected, and [`TotalOrd::new`] returns `None`.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Reject;
+
+impl NanPolicy for Reject {
+    #[inline]
+    fn check<F: Float>(value: F) -> Option<F> {
+        if value.is_nan() {
+            None
+        } else {
+            Some(value)
+        }
+    }
+}
+
+/// NaN is accepted and ordered after every other value, including positive infinity.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct SortLast;
+
+impl NanPolicy for SortLast {
+    #[inline]
+    fn check<F: Float>(value: F) -> Option<F> {
+        if value.is_nan() {
+            // totalOrder places negative NaN first, so clear the sign bit
+            Some(value.abs())
+        } else {
+            Some(value)
+        }
+    }
+}
+
+/// NaN is a bug, and [`TotalOrd::new`] panics.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Panic;
+
+impl NanPolicy for Panic {
+    #[inline]
+    fn check<F: Float>(value: F) -> Option<F> {
+        if value.is_nan() {
+            panic!("NaN is not a valid priority: {:?}", value);
+        }
+        Some(value)
+    }
+}
+
+/// A floating point priority ordered by the IEEE 754 `totalOrder` predicate.
+///
+/// Values are ordered `-inf < ... < -0.0 < +0.0 < ... < +inf`. NaN is handled by the policy `P`.
+#[derive(Debug)]
+pub struct TotalOrd<F, P = SortLast> {
+    value: F,
+    policy: PhantomData<P>,
+}
+
+impl<F: Float, P> Clone for TotalOrd<F, P> {
+    #[inline]
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<F: Float, P> Copy for TotalOrd<F, P> {}
+
+impl<F: Float, P: NanPolicy> TotalOrd<F, P> {
+    /// Wraps `value`, returning `None` if it is NaN and the policy is [`Reject`].
+    #[inline]
+    pub fn new(value: F) -> Option<Self> {
+        P::check(value).map(|value| Self {
+            value,
+            policy: PhantomData,
+        })
+    }
+}
+
+impl<F: Float, P> TotalOrd<F, P> {
+    #[inline]
+    pub fn get(self) -> F {
+        self.value
+    }
+}
+
+impl<F: Float, P> PartialEq for TotalOrd<F, P> {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<F: Float, P> Eq for TotalOrd<F, P> {}
+
+impl<F: Float, P> PartialOrd for TotalOrd<F, P> {
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<F: Float, P> Ord for TotalOrd<F, P> {
+    #[inline]
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.value.total_cmp(&other.value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{get_id, Arena};
+    use crate::IndexedMinQueue;
+
+    fn key(value: f64) -> TotalOrd<f64> {
+        TotalOrd::new(value).unwrap()
+    }
+
+    #[test]
+    fn total_order() {
+        let values = [
+            f64::NEG_INFINITY,
+            -1.0,
+            -0.0,
+            0.0,
+            1.0,
+            f64::INFINITY,
+            f64::NAN,
+        ];
+
+        for pair in values.windows(2) {
+            assert!(key(pair[0]) < key(pair[1]), "{:?}", pair);
+        }
+        assert!(key(-f64::NAN) > key(f64::INFINITY));
+        assert_eq!(key(f64::NAN), key(-f64::NAN));
+    }
+
+    #[test]
+    fn reject_nan() {
+        assert!(TotalOrd::<f32, Reject>::new(f32::NAN).is_none());
+        assert_eq!(
+            Some(1.0),
+            TotalOrd::<f32, Reject>::new(1.0).map(TotalOrd::get)
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn panic_on_nan() {
+        TotalOrd::<f64, Panic>::new(f64::NAN);
+    }
+
+    #[test]
+    fn decrease_to_nan_is_not_an_improvement() {
+        let mut queue = IndexedMinQueue::<Arena, TotalOrd<f64>>::default();
+
+        queue.insert(get_id(0), key(1.0));
+        queue.insert(get_id(1), key(2.0));
+        queue.decrease(get_id(1), key(f64::NAN));
+
+        assert_eq!(Some(2.0), queue[get_id(1)].map(TotalOrd::get));
+
+        queue.increase(get_id(0), key(f64::NAN));
+
+        assert_eq!(Some(get_id(1)), queue.peek_id().map(|(id, _)| id));
+    }
+
+    #[test]
+    fn decrease_from_nan() {
+        let mut queue = IndexedMinQueue::<Arena, TotalOrd<f64>>::default();
+
+        queue.insert(get_id(0), key(f64::NAN));
+        queue.insert(get_id(1), key(2.0));
+        queue.decrease(get_id(0), key(1.0));
+
+        assert_eq!(Some((get_id(0), &key(1.0))), queue.peek_id());
+    }
+
+    #[test]
+    fn decrease_to_negative_zero() {
+        let mut queue = IndexedMinQueue::<Arena, TotalOrd<f64>>::default();
+
+        queue.insert(get_id(0), key(0.0));
+        queue.insert(get_id(1), key(0.0));
+        queue.decrease(get_id(1), key(-0.0));
+
+        let (id, value) = queue.pop().unwrap();
+        assert_eq!(get_id(1), id);
+        assert!(value.get().is_sign_negative());
+    }
+}
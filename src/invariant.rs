@@ -0,0 +1,120 @@
+use gen_id_allocator::untyped::UntypedId;
+use gen_id_component::UntypedComponent;
+use std::fmt::{Display, Formatter};
+
+/// The first broken invariant found by a queue's `validate`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InvariantError {
+    /// The value at `child` is ordered before the value at `parent`, which is its parent in a
+    /// D-ary heap and any of its ancestors in a min-max heap.
+    HeapOrder { parent: usize, child: usize },
+    /// The id at `position` maps back to a different position.
+    PositionMismatch {
+        position: usize,
+        id: UntypedId,
+        mapped: Option<usize>,
+    },
+    /// The slot holds a position that no queued id occupies.
+    DanglingPosition { slot: usize, position: usize },
+    /// The id is queued but has no value.
+    MissingValue { id: UntypedId },
+    /// The id is queued but has no payload.
+    MissingPayload { id: UntypedId },
+    /// The slot holds a value but no queued id.
+    OrphanedValue { slot: usize },
+    /// The queue holds more entries than its capacity.
+    OverCapacity { len: usize, capacity: usize },
+}
+
+impl Display for InvariantError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InvariantError::HeapOrder { parent, child } => write!(
+                f,
+                "value at position {} is ordered before the value above it at position {}",
+                child, parent
+            ),
+            InvariantError::PositionMismatch {
+                position,
+                id,
+                mapped,
+            } => write!(
+                f,
+                "{:?} is at position {} but maps to position {:?}",
+                id, position, mapped
+            ),
+            InvariantError::DanglingPosition { slot, position } => write!(
+                f,
+                "slot {} maps to position {}, which holds a different id",
+                slot, position
+            ),
+            InvariantError::MissingValue { id } => write!(f, "{:?} is queued without a value", id),
+            InvariantError::MissingPayload { id } => {
+                write!(f, "{:?} is queued without a payload", id)
+            }
+            InvariantError::OrphanedValue { slot } => {
+                write!(f, "slot {} holds a value but is not queued", slot)
+            }
+            InvariantError::OverCapacity { len, capacity } => write!(
+                f,
+                "queue holds {} entries but its capacity is {}",
+                len, capacity
+            ),
+        }
+    }
+}
+
+impl std::error::Error for InvariantError {}
+
+/// Checks that `position_map` and `inverse_map` are inverses and that exactly the queued ids have values.
+#[inline]
+pub(crate) fn validate_storage<T>(
+    values: &UntypedComponent<Option<T>>,
+    position_map: &UntypedComponent<Option<u32>>,
+    inverse_map: &[UntypedId],
+) -> Result<(), InvariantError> {
+    for (position, id) in inverse_map.iter().enumerate() {
+        let mapped = position_map
+            .get(*id)
+            .and_then(|mapped| mapped.map(|p| p as usize));
+        if mapped != Some(position) {
+            return Err(InvariantError::PositionMismatch {
+                position,
+                id: *id,
+                mapped,
+            });
+        }
+        if values.get(*id).and_then(Option::as_ref).is_none() {
+            return Err(InvariantError::MissingValue { id: *id });
+        }
+    }
+
+    for (slot, position) in position_map.iter().enumerate() {
+        if let Some(position) = position.map(|p| p as usize) {
+            if inverse_map.get(position).map(UntypedId::index) != Some(slot) {
+                return Err(InvariantError::DanglingPosition { slot, position });
+            }
+        }
+    }
+
+    let positions = position_map.iter().chain(std::iter::repeat(&None));
+    for (slot, (value, position)) in values.iter().zip(positions).enumerate() {
+        if value.is_some() && position.is_none() {
+            return Err(InvariantError::OrphanedValue { slot });
+        }
+    }
+
+    Ok(())
+}
+
+/// Panics on a broken invariant when the `debug_invariants` feature is enabled, and does nothing otherwise.
+#[inline]
+pub(crate) fn debug_check(validate: impl FnOnce() -> Result<(), InvariantError>) {
+    #[cfg(feature = "debug_invariants")]
+    if let Err(error) = validate() {
+        panic!("queue invariant violated: {}", error);
+    }
+
+    #[cfg(not(feature = "debug_invariants"))]
+    let _ = validate;
+}
@@ -0,0 +1,525 @@
+use crate::compare::{Compare, Max, Min};
+use crate::error::QueueError;
+use crate::invariant::{self, InvariantError};
+use crate::storage::Heap;
+use crate::untyped::UntypedIndexedQueue;
+use crate::update::Update;
+use gen_id_allocator::untyped::UntypedId;
+use gen_id_allocator::{Allocator, Id, ValidId};
+use gen_id_component::UntypedComponent;
+use std::marker::PhantomData;
+use std::ops::IndexMut;
+
+/// An Id-indexed min priority queue that stores a payload alongside each priority.
+pub type KeyedMinQueue<Arena, P, V, const D: usize = 8> = KeyedQueue<Arena, P, V, Min, D>;
+
+/// An Id-indexed max priority queue that stores a payload alongside each priority.
+pub type KeyedMaxQueue<Arena, P, V, const D: usize = 8> = KeyedQueue<Arena, P, V, Max, D>;
+
+/// An Id-indexed priority queue that stores a payload alongside each priority.
+///
+/// Payloads do not take part in the ordering and can be modified in place through `get_mut`.
+#[derive(Debug)]
+pub struct KeyedQueue<Arena, P, V, C = Min, const D: usize = 8> {
+    inner: UntypedIndexedQueue<P, C, D>,
+    payloads: UntypedComponent<Option<V>>,
+    arena: PhantomData<Arena>,
+}
+
+impl<Arena, P, V, C: Default, const D: usize> Default for KeyedQueue<Arena, P, V, C, D> {
+    #[inline]
+    fn default() -> Self {
+        Self::with_comparator(C::default())
+    }
+}
+
+impl<Arena, P: Clone, V: Clone, C: Clone, const D: usize> Clone for KeyedQueue<Arena, P, V, C, D> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            payloads: self.payloads.clone(),
+            arena: PhantomData,
+        }
+    }
+
+    #[inline]
+    fn clone_from(&mut self, rhs: &Self) {
+        self.inner.clone_from(&rhs.inner);
+        self.payloads.clone_from(&rhs.payloads);
+    }
+}
+
+impl<Arena, P, V, C, const D: usize> KeyedQueue<Arena, P, V, C, D> {
+    #[inline]
+    pub fn with_comparator(compare: C) -> Self {
+        Self {
+            inner: UntypedIndexedQueue::with_comparator(compare),
+            payloads: Default::default(),
+            arena: PhantomData,
+        }
+    }
+
+    #[inline]
+    pub fn comparator(&self) -> &C {
+        self.inner.comparator()
+    }
+}
+
+impl<Arena, P, V, C: Compare<P>, const D: usize> KeyedQueue<Arena, P, V, C, D> {
+    #[inline]
+    pub fn clear(&mut self) {
+        self.inner.clear();
+        self.payloads.fill_with(|| None);
+        self.check_invariants();
+    }
+
+    /// Inserts or updates the entry for `id`, returning the payload it replaced.
+    #[inline]
+    pub fn insert(
+        &mut self,
+        id: impl ValidId<Arena = Arena>,
+        priority: P,
+        payload: V,
+    ) -> Option<V> {
+        let id = id.id().untyped;
+
+        let previous = if self.inner.get(id).is_some() {
+            self.payloads.index_mut(id).replace(payload)
+        } else {
+            self.payloads.insert(id, Some(payload));
+            None
+        };
+
+        self.inner.insert(id, priority);
+        self.check_invariants();
+
+        previous
+    }
+
+    /// As in [`UntypedIndexedQueue::try_insert`], returning the payload that was replaced.
+    #[inline]
+    pub fn try_insert(
+        &mut self,
+        id: impl ValidId<Arena = Arena>,
+        priority: P,
+        payload: V,
+    ) -> Result<Option<V>, QueueError> {
+        if !self.inner.can_insert(id.id().untyped) {
+            return Err(QueueError::CapacityOverflow);
+        }
+
+        Ok(self.insert(id, priority, payload))
+    }
+
+    #[inline]
+    pub fn remove(&mut self, id: impl ValidId<Arena = Arena>) -> Option<(Id<Arena>, P, V)> {
+        let (id, priority) = self.inner.remove(id.id().untyped)?;
+        let removed = self.with_payload(id, priority);
+        self.check_invariants();
+        removed
+    }
+
+    #[inline]
+    pub fn priority(&self, id: impl ValidId<Arena = Arena>) -> Option<&P> {
+        self.inner.get(id.id().untyped)
+    }
+
+    #[inline]
+    pub fn get(&self, id: impl ValidId<Arena = Arena>) -> Option<&V> {
+        let id = id.id().untyped;
+        self.inner.get(id)?;
+        self.payloads.get(id).and_then(Option::as_ref)
+    }
+
+    /// Returns the payload for `id` mutably. The queue order is unaffected.
+    #[inline]
+    pub fn get_mut(&mut self, id: impl ValidId<Arena = Arena>) -> Option<&mut V> {
+        let id = id.id().untyped;
+        self.inner.get(id)?;
+        self.payloads.index_mut(id).as_mut()
+    }
+
+    #[inline]
+    pub fn peek(&self) -> Option<(Id<Arena>, &P, &V)> {
+        self.get_position(0)
+    }
+
+    #[inline]
+    pub fn get_position(&self, position: usize) -> Option<(Id<Arena>, &P, &V)> {
+        let (id, priority) = self.inner.get_position_with_id(position)?;
+        self.payload_ref(id, priority)
+    }
+
+    #[inline]
+    pub fn pop(&mut self) -> Option<(Id<Arena>, P, V)> {
+        self.remove_position(0)
+    }
+
+    #[inline]
+    pub fn remove_position(&mut self, position: usize) -> Option<(Id<Arena>, P, V)> {
+        let (id, priority) = self.inner.remove_position(position)?;
+        let removed = self.with_payload(id, priority);
+        self.check_invariants();
+        removed
+    }
+
+    /// As in [`UntypedIndexedQueue::try_remove_position`], along with the entry's payload.
+    #[inline]
+    pub fn try_remove_position(
+        &mut self,
+        position: usize,
+    ) -> Result<(Id<Arena>, P, V), QueueError> {
+        let (id, priority) = self.inner.try_remove_position(position)?;
+        let removed = self.with_payload(id, priority).ok_or(QueueError::Absent);
+        self.check_invariants();
+        removed
+    }
+
+    /// Replaces the priority for `id` if `priority` is smaller than it.
+    #[inline]
+    pub fn decrease(&mut self, id: impl ValidId<Arena = Arena>, priority: P) -> Update<P>
+    where
+        P: Ord,
+    {
+        let update = self.inner.decrease(id.id().untyped, priority);
+        self.check_invariants();
+        update
+    }
+
+    /// As in [`UntypedIndexedQueue::try_decrease`].
+    #[inline]
+    pub fn try_decrease(
+        &mut self,
+        id: impl ValidId<Arena = Arena>,
+        priority: P,
+    ) -> Result<P, QueueError>
+    where
+        P: Ord,
+    {
+        self.decrease(id, priority).into_result()
+    }
+
+    /// Replaces the priority for `id` if `priority` is larger than it.
+    #[inline]
+    pub fn increase(&mut self, id: impl ValidId<Arena = Arena>, priority: P) -> Update<P>
+    where
+        P: Ord,
+    {
+        let update = self.inner.increase(id.id().untyped, priority);
+        self.check_invariants();
+        update
+    }
+
+    /// Moves the entry for `id` towards the front of the queue if `priority` is ordered before its current priority.
+    #[inline]
+    pub fn promote(&mut self, id: impl ValidId<Arena = Arena>, priority: P) -> Update<P> {
+        let update = self.inner.promote(id.id().untyped, priority);
+        self.check_invariants();
+        update
+    }
+
+    /// Moves the entry for `id` towards the back of the queue if `priority` is ordered after its current priority.
+    #[inline]
+    pub fn demote(&mut self, id: impl ValidId<Arena = Arena>, priority: P) -> Update<P> {
+        let update = self.inner.demote(id.id().untyped, priority);
+        self.check_invariants();
+        update
+    }
+
+    /// As in [`UntypedIndexedQueue::retain_live`], dropping the payloads of the removed entries.
+    #[inline]
+    pub fn retain_live(&mut self, allocator: &Allocator<Arena>) {
+        self.retain(|id, _, _| allocator.is_alive(id));
+    }
+
+    /// As in [`UntypedIndexedQueue::retain`], with `f` also given each entry's payload.
+    #[inline]
+    pub fn retain(&mut self, mut f: impl FnMut(Id<Arena>, &P, &V) -> bool) {
+        let payloads = &mut self.payloads;
+
+        self.inner.retain(|id, priority| {
+            let keep = match payloads.get(id).and_then(Option::as_ref) {
+                Some(payload) => f(Id::new(id), priority, payload),
+                None => false,
+            };
+            if !keep {
+                payloads.index_mut(id).take();
+            }
+            keep
+        });
+        self.check_invariants();
+    }
+
+    /// As in [`UntypedIndexedQueue::iter_sorted`], along with each entry's payload.
+    #[inline]
+    pub fn iter_sorted(&self) -> impl Iterator<Item = (Id<Arena>, &P, &V)> {
+        self.inner
+            .iter_sorted()
+            .filter_map(move |(id, priority)| self.payload_ref(id, priority))
+    }
+
+    /// As in [`UntypedIndexedQueue::iter_unordered`], along with each entry's payload.
+    #[inline]
+    pub fn iter_unordered(&self) -> impl Iterator<Item = (Id<Arena>, &P, &V)> {
+        self.inner
+            .iter_unordered()
+            .filter_map(move |(id, priority)| self.payload_ref(id, priority))
+    }
+
+    /// As in [`UntypedIndexedQueue::drain_sorted`], along with each entry's payload.
+    #[inline]
+    pub fn drain_sorted(&mut self) -> impl Iterator<Item = (Id<Arena>, P, V)> + '_ {
+        DrainPayloads {
+            entries: self.inner.drain_sorted(),
+            payloads: &mut self.payloads,
+            arena: PhantomData,
+        }
+    }
+
+    /// As in [`UntypedIndexedQueue::drain`], along with each entry's payload.
+    #[inline]
+    pub fn drain(&mut self) -> impl Iterator<Item = (Id<Arena>, P, V)> + '_ {
+        DrainPayloads {
+            entries: self.inner.drain(),
+            payloads: &mut self.payloads,
+            arena: PhantomData,
+        }
+    }
+
+    #[inline]
+    pub fn into_sorted_vec(mut self) -> Vec<(Id<Arena>, P, V)> {
+        self.drain_sorted().collect()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Checks the invariants of the underlying queue and that every queued id has a payload.
+    #[inline]
+    pub fn validate(&self) -> Result<(), InvariantError> {
+        self.inner.validate()?;
+
+        for (id, _) in self.inner.iter_unordered() {
+            if self.payloads.get(*id).and_then(Option::as_ref).is_none() {
+                return Err(InvariantError::MissingPayload { id: *id });
+            }
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    fn check_invariants(&self) {
+        invariant::debug_check(|| self.validate());
+    }
+
+    #[inline]
+    fn payload_ref<'a>(
+        &'a self,
+        id: &UntypedId,
+        priority: &'a P,
+    ) -> Option<(Id<Arena>, &'a P, &'a V)> {
+        let payload = self.payloads.get(*id)?.as_ref()?;
+        Some((Id::new(*id), priority, payload))
+    }
+
+    #[inline]
+    fn with_payload(&mut self, id: UntypedId, priority: P) -> Option<(Id<Arena>, P, V)> {
+        take_payload(&mut self.payloads, id, priority)
+    }
+}
+
+/// Pairs each drained entry with its payload, and clears the payloads of any entries left when dropped.
+struct DrainPayloads<'a, Arena, I, V> {
+    entries: I,
+    payloads: &'a mut UntypedComponent<Option<V>>,
+    arena: PhantomData<Arena>,
+}
+
+impl<'a, Arena, P, V, I: Iterator<Item = (UntypedId, P)>> Iterator
+    for DrainPayloads<'a, Arena, I, V>
+{
+    type Item = (Id<Arena>, P, V);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let (id, priority) = self.entries.next()?;
+        take_payload(self.payloads, id, priority)
+    }
+}
+
+impl<'a, Arena, I, V> Drop for DrainPayloads<'a, Arena, I, V> {
+    #[inline]
+    fn drop(&mut self) {
+        self.payloads.fill_with(|| None);
+    }
+}
+
+#[inline]
+fn take_payload<Arena, P, V>(
+    payloads: &mut UntypedComponent<Option<V>>,
+    id: UntypedId,
+    priority: P,
+) -> Option<(Id<Arena>, P, V)> {
+    let payload = payloads.index_mut(id).take()?;
+    Some((Id::new(id), priority, payload))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::storage::CAPACITY;
+    use crate::test_util::{get_id, Arena};
+
+    #[test]
+    fn pop_returns_priority_and_payload() {
+        let mut queue = KeyedMinQueue::<Arena, u32, &str>::default();
+
+        queue.insert(get_id(0), 2, "b");
+        queue.insert(get_id(1), 1, "a");
+
+        assert_eq!(Some((get_id(1), &1, &"a")), queue.peek());
+        assert_eq!(Some((get_id(1), 1, "a")), queue.pop());
+        assert_eq!(Some((get_id(0), 2, "b")), queue.pop());
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn insert_replaces_payload() {
+        let mut queue = KeyedMinQueue::<Arena, u32, &str>::default();
+
+        assert_eq!(None, queue.insert(get_id(0), 2, "a"));
+        assert_eq!(Some("a"), queue.insert(get_id(0), 1, "b"));
+
+        assert_eq!(Some(&1), queue.priority(get_id(0)));
+        assert_eq!(Some(&"b"), queue.get(get_id(0)));
+        assert_eq!(1, queue.len());
+    }
+
+    #[test]
+    fn get_mut_does_not_reorder() {
+        let mut queue = KeyedMinQueue::<Arena, u32, Vec<u32>>::default();
+
+        queue.insert(get_id(0), 1, vec![]);
+        queue.insert(get_id(1), 2, vec![]);
+
+        queue.get_mut(get_id(1)).unwrap().push(3);
+
+        assert_eq!(Some((get_id(0), &1, &vec![])), queue.peek());
+        assert_eq!(Some(&vec![3]), queue.get(get_id(1)));
+    }
+
+    #[test]
+    fn remove_clears_payload() {
+        let mut queue = KeyedMinQueue::<Arena, u32, &str>::default();
+
+        queue.insert(get_id(0), 1, "a");
+
+        assert_eq!(Some((get_id(0), 1, "a")), queue.remove(get_id(0)));
+        assert_eq!(None, queue.get(get_id(0)));
+        assert_eq!(None, queue.get_mut(get_id(0)));
+    }
+
+    #[test]
+    fn retain_live_drops_dead_payloads() {
+        let mut alloc = Allocator::<Arena>::default();
+        let mut queue = KeyedMinQueue::<Arena, u32, &str>::default();
+
+        let dead = alloc.create();
+        let live = alloc.create();
+        queue.insert(dead, 1, "a");
+        queue.insert(live, 2, "b");
+        alloc.kill(dead);
+        let reused = alloc.create();
+
+        assert_eq!(None, queue.get(reused));
+
+        queue.retain_live(&alloc);
+
+        assert_eq!(1, queue.len());
+        assert_eq!(None, queue.get(dead));
+        assert_eq!(Some((live, &2, &"b")), queue.peek());
+    }
+
+    #[test]
+    fn drain_sorted_dropped_early_clears_payloads() {
+        let mut queue = KeyedMinQueue::<Arena, u32, &str>::default();
+
+        queue.insert(get_id(0), 2, "a");
+        queue.insert(get_id(1), 1, "b");
+
+        assert_eq!(Some((get_id(1), 1, "b")), queue.drain_sorted().next());
+
+        assert!(queue.is_empty());
+        assert_eq!(None, queue.insert(get_id(0), 3, "c"));
+        assert_eq!(vec![(get_id(0), 3, "c")], queue.into_sorted_vec());
+    }
+
+    #[test]
+    fn max_queue_pops_largest_priority() {
+        let mut queue = KeyedMaxQueue::<Arena, u32, &str>::default();
+
+        queue.insert(get_id(0), 1, "a");
+        queue.insert(get_id(1), 2, "b");
+        queue.decrease(get_id(1), 0);
+
+        assert_eq!(Some((get_id(0), 1, "a")), queue.pop());
+        assert_eq!(Some((get_id(1), 0, "b")), queue.pop());
+    }
+
+    #[test]
+    fn validate_detects_missing_payload() {
+        let mut queue = KeyedMinQueue::<Arena, u32, &str>::default();
+        queue.insert(get_id(0), 1, "a");
+        queue.insert(get_id(1), 1, "b");
+        assert_eq!(Ok(()), queue.validate());
+
+        queue.payloads.index_mut(UntypedId::first(1)).take();
+
+        assert_eq!(
+            Err(InvariantError::MissingPayload {
+                id: UntypedId::first(1)
+            }),
+            queue.validate()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "debug_invariants")]
+    #[should_panic(expected = "queued without a payload")]
+    fn mutations_check_payloads() {
+        let mut queue = KeyedMinQueue::<Arena, u32, &str>::default();
+        queue.insert(get_id(0), 1, "a");
+        queue.insert(get_id(1), 2, "b");
+
+        queue.payloads.index_mut(UntypedId::first(1)).take();
+
+        queue.promote(get_id(0), 0);
+    }
+
+    #[test]
+    fn try_insert_keeps_payloads() {
+        let capacity = CAPACITY as usize;
+        let mut queue = KeyedMinQueue::<Arena, u32, &str>::default();
+        assert_eq!(Ok(None), queue.try_insert(get_id(0), 3, "a"));
+        assert_eq!(Ok(Some("a")), queue.try_insert(get_id(0), 2, "b"));
+        for i in 1..capacity {
+            queue.insert(get_id(i), 5, "c");
+        }
+
+        assert_eq!(
+            Err(QueueError::CapacityOverflow),
+            queue.try_insert(get_id(capacity), 1, "d")
+        );
+        assert_eq!(None, queue.get(get_id(capacity)));
+        assert_eq!(Ok((get_id(0), 2, "b")), queue.try_remove_position(0));
+    }
+}
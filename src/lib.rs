@@ -1,20 +1,58 @@
-use crate::untyped::UntypedIndexedMinQueue;
-use gen_id_allocator::{Id, ValidId};
+use crate::untyped::UntypedIndexedQueue;
+use gen_id_allocator::{Allocator, Id, ValidId};
+use gen_id_component::Component;
 use iter_context::ContextualIterator;
-use std::cmp::Reverse;
+use std::iter::FromIterator;
 use std::marker::PhantomData;
 use std::ops::Index;
 
-mod untyped;
+pub use bounded::{BoundedIndexedQueue, Insertion};
+pub use compare::{by_key, ByKey, Compare, Max, Min};
+pub use entry::{Entry, OccupiedEntry, VacantEntry};
+pub use error::QueueError;
+pub use float::{Float, NanPolicy, Panic, Reject, SortLast, TotalOrd};
+pub use invariant::InvariantError;
+pub use keyed::{KeyedMaxQueue, KeyedMinQueue, KeyedQueue};
+pub use minmax::IndexedMinMaxQueue;
+pub use snapshot::{SnapshotError, SnapshotValue, SNAPSHOT_VERSION};
+pub use stable::{StableIndexedMaxQueue, StableIndexedMinQueue, StableIndexedQueue};
+pub use update::Update;
+
+mod bounded;
+mod compare;
+mod entry;
+mod error;
+mod float;
+mod invariant;
+mod keyed;
+mod minmax;
+#[cfg(feature = "serde")]
+mod serialize;
+mod snapshot;
+mod stable;
+mod storage;
+#[cfg(test)]
+mod test_util;
+pub mod untyped;
+mod update;
 
 /// An Id-indexed min priority queue based on a D-ary heap.
+pub type IndexedMinQueue<Arena, T, const D: usize = 8> = IndexedQueue<Arena, T, Min, D>;
+
+/// An Id-indexed max priority queue based on a D-ary heap.
+pub type IndexedMaxQueue<Arena, T, const D: usize = 8> = IndexedQueue<Arena, T, Max, D>;
+
+/// An Id-indexed priority queue based on a D-ary heap, ordered by the comparator `C`.
+///
+/// `D` sets the number of children per node. Wider heaps are shallower, making insertion and
+/// `decrease` cheaper at the cost of more comparisons when popping.
 #[derive(Debug)]
-pub struct IndexedMinQueue<Arena, T> {
-    inner: UntypedIndexedMinQueue<T>,
+pub struct IndexedQueue<Arena, T, C = Min, const D: usize = 8> {
+    inner: UntypedIndexedQueue<T, C, D>,
     arena: PhantomData<Arena>,
 }
 
-impl<Arena, T> Default for IndexedMinQueue<Arena, T> {
+impl<Arena, T, C: Default, const D: usize> Default for IndexedQueue<Arena, T, C, D> {
     #[inline]
     fn default() -> Self {
         Self {
@@ -24,7 +62,7 @@ impl<Arena, T> Default for IndexedMinQueue<Arena, T> {
     }
 }
 
-impl<Arena, T: Clone> Clone for IndexedMinQueue<Arena, T> {
+impl<Arena, T: Clone, C: Clone, const D: usize> Clone for IndexedQueue<Arena, T, C, D> {
     #[inline]
     fn clone(&self) -> Self {
         Self {
@@ -39,7 +77,50 @@ impl<Arena, T: Clone> Clone for IndexedMinQueue<Arena, T> {
     }
 }
 
-impl<Arena, T: Ord + Copy> IndexedMinQueue<Arena, T> {
+impl<Arena, T, C, const D: usize> IndexedQueue<Arena, T, C, D> {
+    #[inline]
+    pub fn with_comparator(compare: C) -> Self {
+        Self {
+            inner: UntypedIndexedQueue::with_comparator(compare),
+            arena: PhantomData,
+        }
+    }
+
+    #[inline]
+    pub fn comparator(&self) -> &C {
+        self.inner.comparator()
+    }
+}
+
+impl<Arena, T: Clone, C: Compare<T> + Default, const D: usize> IndexedQueue<Arena, T, C, D> {
+    /// Builds a queue from the values of the living ids in `component`, heapifying in O(n).
+    ///
+    /// A component is indexed by slot alone, so `allocator` supplies the current generation of each id.
+    #[inline]
+    pub fn from_component(component: &Component<Arena, T>, allocator: &Allocator<Arena>) -> Self {
+        allocator
+            .ids()
+            .filter_map(|id| component.get(id).map(|value| (id, value.clone())))
+            .collect()
+    }
+
+    /// Builds a queue from the values of the living ids in `component`, skipping `None` slots.
+    #[inline]
+    pub fn from_optional_component(
+        component: &Component<Arena, Option<T>>,
+        allocator: &Allocator<Arena>,
+    ) -> Self {
+        allocator
+            .ids()
+            .filter_map(|id| {
+                let value = component.get(id)?.as_ref()?;
+                Some((id, value.clone()))
+            })
+            .collect()
+    }
+}
+
+impl<Arena, T, C: Compare<T>, const D: usize> IndexedQueue<Arena, T, C, D> {
     #[inline]
     pub fn clear(&mut self) {
         self.inner.clear();
@@ -50,6 +131,22 @@ impl<Arena, T: Ord + Copy> IndexedMinQueue<Arena, T> {
         self.inner.insert(id.id().untyped, value);
     }
 
+    /// As in [`UntypedIndexedQueue::try_insert`].
+    #[inline]
+    pub fn try_insert(
+        &mut self,
+        id: impl ValidId<Arena = Arena>,
+        value: T,
+    ) -> Result<(), QueueError> {
+        self.inner.try_insert(id.id().untyped, value)
+    }
+
+    /// Gets the entry for `id` for insertion or in-place modification.
+    #[inline]
+    pub fn entry(&mut self, id: impl ValidId<Arena = Arena>) -> Entry<'_, Arena, T, C, D> {
+        Entry::new(&mut self.inner, id.id().untyped)
+    }
+
     #[inline]
     pub fn remove(&mut self, id: impl ValidId<Arena = Arena>) -> Option<(Id<Arena>, T)> {
         self.inner
@@ -67,6 +164,8 @@ impl<Arena, T: Ord + Copy> IndexedMinQueue<Arena, T> {
         self.get_position_with_id(0)
     }
 
+    /// Returns the value in heap slot `position`. Only slot 0 is guaranteed to match its rank in
+    /// priority order; use [`nth_in_order`](Self::nth_in_order) to query by rank.
     #[inline]
     pub fn get_position(&self, position: usize) -> Option<&T> {
         self.inner.get_position(position)
@@ -91,141 +190,194 @@ impl<Arena, T: Ord + Copy> IndexedMinQueue<Arena, T> {
             .map(|(id, value)| (Id::new(id), value))
     }
 
+    /// As in [`UntypedIndexedQueue::try_remove_position`].
     #[inline]
-    pub fn decrease(&mut self, id: impl ValidId<Arena = Arena>, value: T) {
-        self.inner.decrease(id.id().untyped, value);
+    pub fn try_remove_position(&mut self, position: usize) -> Result<(Id<Arena>, T), QueueError> {
+        self.inner
+            .try_remove_position(position)
+            .map(|(id, value)| (Id::new(id), value))
     }
 
+    /// Replaces the value for `id` if `value` is smaller than it. On a max queue this moves the
+    /// entry towards the back.
     #[inline]
-    pub fn increase(&mut self, id: impl ValidId<Arena = Arena>, value: T) {
-        self.inner.increase(id.id().untyped, value);
+    pub fn decrease(&mut self, id: impl ValidId<Arena = Arena>, value: T) -> Update<T>
+    where
+        T: Ord,
+    {
+        self.inner.decrease(id.id().untyped, value)
     }
 
+    /// As in [`UntypedIndexedQueue::try_decrease`].
     #[inline]
-    pub fn iter_sorted(&self) -> impl Iterator<Item = (Id<Arena>, &T)> {
-        self.inner
-            .iter_sorted()
-            .map(|(id, value)| (Id::new(*id), value))
+    pub fn try_decrease(
+        &mut self,
+        id: impl ValidId<Arena = Arena>,
+        value: T,
+    ) -> Result<T, QueueError>
+    where
+        T: Ord,
+    {
+        self.inner.try_decrease(id.id().untyped, value)
     }
 
+    /// Replaces the value for `id` if `value` is larger than it. On a max queue this moves the
+    /// entry towards the front.
     #[inline]
-    pub fn len(&self) -> usize {
-        self.inner.len()
+    pub fn increase(&mut self, id: impl ValidId<Arena = Arena>, value: T) -> Update<T>
+    where
+        T: Ord,
+    {
+        self.inner.increase(id.id().untyped, value)
     }
 
+    /// Moves the entry for `id` towards the front of the queue if `value` is ordered before its current value.
     #[inline]
-    pub fn is_empty(&self) -> bool {
-        self.inner.is_empty()
+    pub fn promote(&mut self, id: impl ValidId<Arena = Arena>, value: T) -> Update<T> {
+        self.inner.promote(id.id().untyped, value)
     }
-}
-
-impl<Arena, T, V: ValidId<Arena = Arena>> Index<V> for IndexedMinQueue<Arena, T> {
-    type Output = Option<T>;
 
+    /// Moves the entry for `id` towards the back of the queue if `value` is ordered after its current value.
     #[inline]
-    fn index(&self, index: V) -> &Self::Output {
-        self.inner.index(index.id().untyped)
+    pub fn demote(&mut self, id: impl ValidId<Arena = Arena>, value: T) -> Update<T> {
+        self.inner.demote(id.id().untyped, value)
     }
-}
 
-impl<'a, Arena, T> IntoIterator for &'a IndexedMinQueue<Arena, T> {
-    type Item = &'a Option<T>;
-    type IntoIter = std::slice::Iter<'a, Option<T>>;
-
-    fn into_iter(self) -> Self::IntoIter {
-        self.inner.into_iter()
+    /// Inserts the entry if `id` is absent, otherwise decreases it. Returns true if the queue changed.
+    #[inline]
+    pub fn push_or_decrease(&mut self, id: impl ValidId<Arena = Arena>, value: T) -> bool
+    where
+        T: Ord,
+    {
+        self.inner.push_or_decrease(id.id().untyped, value)
     }
-}
 
-impl<'a, Arena, T> ContextualIterator for &'a IndexedMinQueue<Arena, T> {
-    type Context = Arena;
-}
+    /// Inserts the entry if `id` is absent, otherwise promotes it. Returns true if the queue changed.
+    #[inline]
+    pub fn push_or_promote(&mut self, id: impl ValidId<Arena = Arena>, value: T) -> bool {
+        self.inner.push_or_promote(id.id().untyped, value)
+    }
 
-/// An Id-indexed max priority queue based on a D-ary heap.
-#[derive(Debug)]
-pub struct IndexedMaxQueue<Arena, T> {
-    inner: IndexedMinQueue<Arena, Reverse<T>>,
-}
+    /// As in [`UntypedIndexedQueue::retain_live`].
+    #[inline]
+    pub fn retain_live(&mut self, allocator: &Allocator<Arena>) {
+        self.inner.retain(|id, _| allocator.is_alive(Id::new(id)));
+    }
 
-impl<Arena, T> Default for IndexedMaxQueue<Arena, T> {
+    /// As in [`UntypedIndexedQueue::retain`].
     #[inline]
-    fn default() -> Self {
-        Self {
-            inner: Default::default(),
-        }
+    pub fn retain(&mut self, mut f: impl FnMut(Id<Arena>, &T) -> bool) {
+        self.inner.retain(|id, value| f(Id::new(id), value));
     }
-}
 
-impl<Arena, T: Clone> Clone for IndexedMaxQueue<Arena, T> {
+    /// As in [`UntypedIndexedQueue::retain_mut`].
     #[inline]
-    fn clone(&self) -> Self {
-        Self {
-            inner: self.inner.clone(),
-        }
+    pub fn retain_mut(&mut self, mut f: impl FnMut(Id<Arena>, &mut T) -> bool) {
+        self.inner.retain_mut(|id, value| f(Id::new(id), value));
     }
 
+    /// Moves every entry of `other` into the queue in O(n + m), leaving `other` empty.
+    ///
+    /// An id that is in both queues takes the value from `other`. If the two queues hold different
+    /// generations of a slot, the entry for the newer generation is kept.
     #[inline]
-    fn clone_from(&mut self, rhs: &Self) {
-        self.inner.clone_from(&rhs.inner);
+    pub fn append(&mut self, other: &mut Self) {
+        self.inner.append(&mut other.inner);
     }
-}
 
-impl<Arena, T: Ord + Copy> IndexedMaxQueue<Arena, T> {
+    /// Moves every entry of `other` into the queue in O(n + m).
+    ///
+    /// An id that is in both queues takes the value returned by `resolve(current, other)`, such as
+    /// `std::cmp::min` to keep the smaller value or `|current, _| current` to keep this queue's value.
+    /// If the two queues hold different generations of a slot, the entry for the newer generation
+    /// is kept without calling `resolve`.
     #[inline]
-    pub fn clear(&mut self) {
-        self.inner.clear();
+    pub fn merge_with(&mut self, other: Self, resolve: impl FnMut(T, T) -> T) {
+        self.inner.merge_with(other.inner, resolve);
     }
 
+    /// As in [`UntypedIndexedQueue::iter_sorted`].
     #[inline]
-    pub fn insert(&mut self, id: impl ValidId<Arena = Arena>, value: T) {
-        self.inner.insert(id, Reverse(value));
+    pub fn iter_sorted(&self) -> impl Iterator<Item = (Id<Arena>, &T)> {
+        self.inner
+            .iter_sorted()
+            .map(|(id, value)| (Id::new(*id), value))
     }
 
+    /// Returns the first `k` entries in priority order, without modifying the queue.
+    ///
+    /// Takes O(k·D·log(k·D)), as each entry taken pushes its `D` children onto a side heap.
     #[inline]
-    pub fn remove(&mut self, id: impl ValidId<Arena = Arena>) -> Option<(Id<Arena>, T)> {
-        self.inner.remove(id).map(|(id, rev)| (id, rev.0))
+    pub fn top_k(&self, k: usize) -> Vec<(Id<Arena>, &T)> {
+        self.iter_sorted().take(k).collect()
     }
 
+    /// Returns the entry at rank `n` in the order of the comparator, counting from zero, so on
+    /// a max queue this is the `n`-th largest entry.
+    ///
+    /// Takes O(n·D·log(n·D)), as in [`top_k`](Self::top_k).
     #[inline]
-    pub fn peek(&self) -> Option<&T> {
-        self.inner.peek().map(|rev| &rev.0)
+    pub fn nth_in_order(&self, n: usize) -> Option<(Id<Arena>, &T)> {
+        self.iter_sorted().nth(n)
     }
 
+    /// As in [`UntypedIndexedQueue::iter_unordered`].
     #[inline]
-    pub fn peek_id(&self) -> Option<(Id<Arena>, &T)> {
-        self.inner.peek_id().map(|rev| (rev.0, &rev.1 .0))
+    pub fn iter_unordered(&self) -> impl Iterator<Item = (Id<Arena>, &T)> {
+        self.inner
+            .iter_unordered()
+            .map(|(id, value)| (Id::new(*id), value))
     }
 
+    /// Lazily pops entries from the front of the queue while `f` returns true for their value.
     #[inline]
-    pub fn get_position(&self, position: usize) -> Option<&T> {
-        self.inner.get_position(position).map(|rev| &rev.0)
+    pub fn pop_while<'a>(
+        &'a mut self,
+        f: impl FnMut(&T) -> bool + 'a,
+    ) -> impl Iterator<Item = (Id<Arena>, T)> + 'a {
+        self.inner
+            .pop_while(f)
+            .map(|(id, value)| (Id::new(id), value))
     }
 
+    /// Lazily pops the entries that are ordered at or before `threshold`.
+    ///
+    /// On a min queue of timestamps, `pop_until(now)` yields every entry that is due.
     #[inline]
-    pub fn get_position_with_id(&self, position: usize) -> Option<(Id<Arena>, &T)> {
+    pub fn pop_until(&mut self, threshold: T) -> impl Iterator<Item = (Id<Arena>, T)> + '_ {
         self.inner
-            .get_position_with_id(position)
-            .map(|(id, value)| (id, &value.0))
+            .pop_until(threshold)
+            .map(|(id, value)| (Id::new(id), value))
     }
 
+    /// As in [`UntypedIndexedQueue::peek_while`].
     #[inline]
-    pub fn pop(&mut self) -> Option<(Id<Arena>, T)> {
-        self.inner.pop().map(|(id, rev)| (id, rev.0))
+    pub fn peek_while<'a>(
+        &'a self,
+        f: impl FnMut(&T) -> bool + 'a,
+    ) -> impl Iterator<Item = (Id<Arena>, &'a T)> + 'a {
+        self.inner
+            .peek_while(f)
+            .map(|(id, value)| (Id::new(*id), value))
     }
 
+    /// As in [`UntypedIndexedQueue::drain_sorted`].
     #[inline]
-    pub fn increase(&mut self, id: impl ValidId<Arena = Arena>, value: T) {
-        self.inner.decrease(id, Reverse(value));
+    pub fn drain_sorted(&mut self) -> impl Iterator<Item = (Id<Arena>, T)> + '_ {
+        self.inner
+            .drain_sorted()
+            .map(|(id, value)| (Id::new(id), value))
     }
 
+    /// As in [`UntypedIndexedQueue::drain`].
     #[inline]
-    pub fn decrease(&mut self, id: impl ValidId<Arena = Arena>, value: T) {
-        self.inner.increase(id, Reverse(value));
+    pub fn drain(&mut self) -> impl Iterator<Item = (Id<Arena>, T)> + '_ {
+        self.inner.drain().map(|(id, value)| (Id::new(id), value))
     }
 
     #[inline]
-    pub fn iter_sorted(&self) -> impl Iterator<Item = (Id<Arena>, &T)> {
-        self.inner.iter_sorted().map(|(id, value)| (id, &value.0))
+    pub fn into_sorted_vec(mut self) -> Vec<(Id<Arena>, T)> {
+        self.drain_sorted().collect()
     }
 
     #[inline]
@@ -235,15 +387,424 @@ impl<Arena, T: Ord + Copy> IndexedMaxQueue<Arena, T> {
 
     #[inline]
     pub fn is_empty(&self) -> bool {
-        self.len() == 0
+        self.inner.is_empty()
+    }
+
+    /// Checks the invariants of the underlying queue, as in [`UntypedIndexedQueue::validate`].
+    #[inline]
+    pub fn validate(&self) -> Result<(), InvariantError> {
+        self.inner.validate()
     }
 }
 
-impl<Arena, T, V: ValidId<Arena = Arena>> Index<V> for IndexedMaxQueue<Arena, T> {
-    type Output = Option<Reverse<T>>;
+impl<Arena, T, C, V: ValidId<Arena = Arena>, const D: usize> Index<V>
+    for IndexedQueue<Arena, T, C, D>
+{
+    type Output = Option<T>;
 
     #[inline]
     fn index(&self, index: V) -> &Self::Output {
-        self.inner.index(index)
+        self.inner.index(index.id().untyped)
+    }
+}
+
+/// Adds every entry as in the `Extend` impl of [`UntypedIndexedQueue`], swimming small batches and rebuilding for large ones.
+impl<Arena, T, C: Compare<T>, const D: usize> Extend<(Id<Arena>, T)>
+    for IndexedQueue<Arena, T, C, D>
+{
+    #[inline]
+    fn extend<I: IntoIterator<Item = (Id<Arena>, T)>>(&mut self, iter: I) {
+        self.inner
+            .extend(iter.into_iter().map(|(id, value)| (id.untyped, value)));
+    }
+}
+
+impl<Arena, T, C: Compare<T> + Default, const D: usize> FromIterator<(Id<Arena>, T)>
+    for IndexedQueue<Arena, T, C, D>
+{
+    #[inline]
+    fn from_iter<I: IntoIterator<Item = (Id<Arena>, T)>>(iter: I) -> Self {
+        let mut queue = Self::default();
+        queue.extend(iter);
+        queue
+    }
+}
+
+/// Yields the entries in heap order.
+impl<Arena, T, C, const D: usize> IntoIterator for IndexedQueue<Arena, T, C, D> {
+    type Item = (Id<Arena>, T);
+    type IntoIter = IntoIter<Arena, T>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            inner: self.inner.into_iter(),
+            arena: PhantomData,
+        }
+    }
+}
+
+/// An owning iterator over the entries of a queue in heap order.
+#[derive(Debug)]
+pub struct IntoIter<Arena, T> {
+    inner: untyped::IntoIter<T>,
+    arena: PhantomData<Arena>,
+}
+
+impl<Arena, T> Iterator for IntoIter<Arena, T> {
+    type Item = (Id<Arena>, T);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(id, value)| (Id::new(id), value))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<Arena, T> ExactSizeIterator for IntoIter<Arena, T> {}
+
+impl<'a, Arena, T, C, const D: usize> IntoIterator for &'a IndexedQueue<Arena, T, C, D> {
+    type Item = &'a Option<T>;
+    type IntoIter = std::slice::Iter<'a, Option<T>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        (&self.inner).into_iter()
+    }
+}
+
+impl<Arena, T, C, const D: usize> ContextualIterator for &IndexedQueue<Arena, T, C, D> {
+    type Context = Arena;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{get_id, Arena};
+
+    #[test]
+    fn from_component_skips_dead_ids() {
+        let mut alloc = Allocator::<Arena>::default();
+        let mut component = Component::<Arena, u32>::default();
+
+        let ids = (0..4).map(|_| alloc.create()).collect::<Vec<_>>();
+        for (value, id) in ids.iter().rev().enumerate() {
+            component.insert(id, value as u32);
+        }
+        alloc.kill(ids[0]);
+
+        let mut queue = IndexedMinQueue::<Arena, u32>::from_component(&component, &alloc);
+
+        assert_eq!(3, queue.len());
+        assert_eq!(None, queue[ids[0]]);
+        assert_eq!(Some((ids[3], 0)), queue.pop());
+        assert_eq!(Some((ids[2], 1)), queue.pop());
+        assert_eq!(Some((ids[1], 2)), queue.pop());
+    }
+
+    #[test]
+    fn from_optional_component_skips_none() {
+        let mut alloc = Allocator::<Arena>::default();
+        let mut component = Component::<Arena, Option<u32>>::default();
+
+        let a = alloc.create();
+        let b = alloc.create();
+        let c = alloc.create();
+        component.insert(a, Some(2));
+        component.insert(b, None);
+        component.insert(c, Some(1));
+
+        let queue = IndexedMaxQueue::<Arena, u32>::from_optional_component(&component, &alloc);
+
+        assert_eq!(2, queue.len());
+        assert_eq!(None, queue[b]);
+        assert_eq!(Some((a, &2)), queue.peek_id());
+    }
+
+    #[test]
+    fn into_iter_yields_every_entry() {
+        let mut alloc = Allocator::<Arena>::default();
+        let ids = (0..3).map(|_| alloc.create()).collect::<Vec<_>>();
+        let queue = ids
+            .iter()
+            .map(|id| (*id, id.untyped.index()))
+            .collect::<IndexedMinQueue<Arena, usize>>();
+
+        let mut entries = queue
+            .into_iter()
+            .map(|(_, value)| value)
+            .collect::<Vec<_>>();
+        entries.sort_unstable();
+
+        assert_eq!(vec![0, 1, 2], entries);
+    }
+
+    fn timestamps() -> IndexedMinQueue<Arena, u32> {
+        vec![5, 1, 3, 8, 3]
+            .into_iter()
+            .enumerate()
+            .map(|(i, time)| (get_id(i), time))
+            .collect()
+    }
+
+    #[test]
+    fn pop_until_yields_due_entries() {
+        let mut queue = timestamps();
+
+        let due = queue.pop_until(3).collect::<Vec<_>>();
+
+        assert_eq!(
+            vec![1, 3, 3],
+            due.iter().map(|(_, t)| *t).collect::<Vec<_>>()
+        );
+        assert_eq!(Some(&5), queue.peek());
+        assert_eq!(0, queue.pop_until(4).count());
+    }
+
+    #[test]
+    fn pop_until_on_max_queue_yields_entries_at_or_above_threshold() {
+        let mut queue = IndexedMaxQueue::<Arena, u32>::default();
+        queue.extend(timestamps());
+
+        let popped = queue.pop_until(5).map(|(_, t)| t).collect::<Vec<_>>();
+
+        assert_eq!(vec![8, 5], popped);
+        assert_eq!(3, queue.len());
+    }
+
+    #[test]
+    fn pop_while_is_lazy() {
+        let mut queue = timestamps();
+
+        assert_eq!(Some((get_id(1), 1)), queue.pop_while(|t| *t < 4).next());
+        assert_eq!(4, queue.len());
+
+        assert_eq!(2, queue.pop_while(|t| *t < 4).count());
+        assert_eq!(2, queue.len());
+    }
+
+    #[test]
+    fn peek_while_does_not_modify_the_queue() {
+        let queue = timestamps();
+
+        let due = queue
+            .peek_while(|t| *t <= 3)
+            .map(|(_, t)| *t)
+            .collect::<Vec<_>>();
+
+        assert_eq!(vec![1, 3, 3], due);
+        assert_eq!(5, queue.len());
+    }
+
+    #[test]
+    fn top_k_returns_entries_in_priority_order() {
+        let queue = (0..20)
+            .map(|i| (get_id(i), (i as u32 * 7) % 20))
+            .collect::<IndexedMinQueue<Arena, u32>>();
+
+        let top = queue
+            .top_k(4)
+            .into_iter()
+            .map(|(_, v)| *v)
+            .collect::<Vec<_>>();
+
+        assert_eq!(vec![0, 1, 2, 3], top);
+        assert_eq!(20, queue.top_k(30).len());
+        assert!(queue.top_k(0).is_empty());
+        assert_eq!(20, queue.len());
+    }
+
+    #[test]
+    fn nth_in_order_counts_from_zero() {
+        let queue = timestamps();
+
+        assert_eq!(Some((get_id(1), &1)), queue.nth_in_order(0));
+        assert_eq!(Some(&5), queue.nth_in_order(3).map(|(_, t)| t));
+        assert_eq!(Some((get_id(3), &8)), queue.nth_in_order(4));
+        assert_eq!(None, queue.nth_in_order(5));
+    }
+
+    #[test]
+    fn nth_in_order_on_max_queue_is_nth_largest() {
+        let mut queue = IndexedMaxQueue::<Arena, u32>::default();
+        queue.extend(timestamps());
+
+        assert_eq!(Some(&8), queue.nth_in_order(0).map(|(_, t)| t));
+        assert_eq!(Some(&5), queue.nth_in_order(1).map(|(_, t)| t));
+    }
+
+    /// Returns a value that is ordered at `rank` by `C`, so each test reads the same through both aliases.
+    fn ranked<C: Compare<u32> + Default>(rank: u32) -> u32 {
+        if C::default().compare(&0, &1) == std::cmp::Ordering::Less {
+            rank
+        } else {
+            100 - rank
+        }
+    }
+
+    fn new_queue<C: Compare<u32> + Default>(ranks: &[u32]) -> IndexedQueue<Arena, u32, C> {
+        ranks
+            .iter()
+            .enumerate()
+            .map(|(i, rank)| (get_id(i), ranked::<C>(*rank)))
+            .collect()
+    }
+
+    fn popped_ids<C: Compare<u32>>(queue: &mut IndexedQueue<Arena, u32, C>) -> Vec<Id<Arena>> {
+        let len = queue.len();
+        (0..len)
+            .filter_map(|_| queue.pop())
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    fn parity_insert_out_of_order<C: Compare<u32> + Default>() {
+        let mut queue = new_queue::<C>(&[3, 2]);
+
+        assert_eq!(vec![get_id(1), get_id(0)], popped_ids(&mut queue));
+    }
+
+    fn parity_re_insert<C: Compare<u32> + Default>() {
+        let mut queue = new_queue::<C>(&[3, 2]);
+
+        queue.insert(get_id(1), ranked::<C>(4));
+
+        assert_eq!(vec![get_id(0), get_id(1)], popped_ids(&mut queue));
+    }
+
+    fn parity_remove<C: Compare<u32> + Default>() {
+        let mut queue = new_queue::<C>(&[1, 2, 3, 4]);
+
+        assert_eq!(Some((get_id(1), ranked::<C>(2))), queue.remove(get_id(1)));
+        assert_eq!(None, queue.remove(get_id(1)));
+        assert_eq!(
+            vec![get_id(0), get_id(2), get_id(3)],
+            popped_ids(&mut queue)
+        );
+    }
+
+    fn parity_remove_position<C: Compare<u32> + Default>() {
+        let mut queue = new_queue::<C>(&[2, 1, 3]);
+
+        assert_eq!(Some((get_id(1), ranked::<C>(1))), queue.remove_position(0));
+        assert_eq!(None, queue.remove_position(2));
+        assert_eq!(vec![get_id(0), get_id(2)], popped_ids(&mut queue));
+    }
+
+    fn parity_promote<C: Compare<u32> + Default>() {
+        let mut queue = new_queue::<C>(&[1, 2, 3]);
+
+        assert!(queue.promote(get_id(2), ranked::<C>(0)).is_updated());
+        assert!(!queue.promote(get_id(1), ranked::<C>(4)).is_updated());
+
+        assert_eq!(
+            vec![get_id(2), get_id(0), get_id(1)],
+            popped_ids(&mut queue)
+        );
+    }
+
+    fn parity_demote<C: Compare<u32> + Default>() {
+        let mut queue = new_queue::<C>(&[1, 2, 3]);
+
+        assert!(queue.demote(get_id(0), ranked::<C>(4)).is_updated());
+        assert!(!queue.demote(get_id(1), ranked::<C>(0)).is_updated());
+
+        assert_eq!(
+            vec![get_id(1), get_id(2), get_id(0)],
+            popped_ids(&mut queue)
+        );
+    }
+
+    fn parity_index<C: Compare<u32> + Default>() {
+        let queue = new_queue::<C>(&[1, 2]);
+
+        assert_eq!(Some(ranked::<C>(2)), queue[get_id(1)]);
+        assert_eq!(None, queue[get_id(2)]);
+    }
+
+    fn parity_iter_values<C: Compare<u32> + Default>() {
+        let queue = new_queue::<C>(&[2, 1]);
+
+        let values = (&queue).into_iter().copied().collect::<Vec<_>>();
+
+        assert_eq!(vec![Some(ranked::<C>(2)), Some(ranked::<C>(1))], values);
+    }
+
+    fn parity_iter_sorted<C: Compare<u32> + Default>() {
+        let queue = new_queue::<C>(&[3, 1, 2]);
+
+        let ids = queue.iter_sorted().map(|(id, _)| id).collect::<Vec<_>>();
+
+        assert_eq!(vec![get_id(1), get_id(2), get_id(0)], ids);
+    }
+
+    fn parity_contextual_iterator<C: Compare<u32> + Default>() {
+        fn context<I: ContextualIterator<Context = Arena>>(iter: I) -> usize {
+            iter.into_iter().count()
+        }
+
+        assert_eq!(2, context(&new_queue::<C>(&[1, 2])));
+    }
+
+    macro_rules! test_parity {
+        ($($test:ident),* $(,)?) => {
+            mod parity {
+                $(
+                    mod $test {
+                        #[test]
+                        fn min() {
+                            super::super::$test::<crate::Min>();
+                        }
+
+                        #[test]
+                        fn max() {
+                            super::super::$test::<crate::Max>();
+                        }
+                    }
+                )*
+            }
+        };
+    }
+
+    test_parity!(
+        parity_insert_out_of_order,
+        parity_re_insert,
+        parity_remove,
+        parity_remove_position,
+        parity_promote,
+        parity_demote,
+        parity_index,
+        parity_iter_values,
+        parity_iter_sorted,
+        parity_contextual_iterator,
+    );
+
+    #[test]
+    fn max_queue_alias_exposes_plain_values() {
+        fn context<I: ContextualIterator<Context = Arena>>(iter: I) -> usize {
+            iter.into_iter().count()
+        }
+
+        let mut queue = IndexedMaxQueue::<Arena, u32>::default();
+        queue.insert(get_id(0), 2);
+        queue.insert(get_id(1), 3);
+        queue.insert(get_id(2), 1);
+
+        let value: Option<u32> = queue[get_id(0)];
+        assert_eq!(Some(2), value);
+
+        let values: Vec<&Option<u32>> = (&queue).into_iter().collect();
+        assert_eq!(vec![&Some(2), &Some(3), &Some(1)], values);
+        assert_eq!(3, context(&queue));
+        assert_eq!(Update::Updated { old: 1 }, queue.decrease(get_id(2), 0));
+
+        assert_eq!(Some((get_id(1), 3)), queue.remove_position(0));
+        assert_eq!(
+            vec![(get_id(0), 2), (get_id(2), 0)],
+            queue.into_sorted_vec()
+        );
     }
 }
@@ -0,0 +1,816 @@
+use crate::compare::{Compare, Min};
+use crate::error::QueueError;
+use crate::invariant::{self, InvariantError};
+use crate::storage::{Heap, Storage};
+use crate::untyped::{get_children, get_parent};
+use crate::update::Update;
+use gen_id_allocator::untyped::UntypedId;
+use gen_id_allocator::{Allocator, Id, ValidId};
+use std::cmp::Ordering;
+use std::iter::FromIterator;
+use std::marker::PhantomData;
+use std::ops::Index;
+
+/// An Id-indexed double-ended priority queue based on a min-max heap, ordered by the comparator `C`.
+///
+/// Even levels of the heap hold the front-most value of their subtree and odd levels hold the
+/// back-most, so both ends of the queue can be peeked in O(1) and popped in O(log n).
+#[derive(Debug)]
+pub struct IndexedMinMaxQueue<Arena, T, C = Min> {
+    /// The values and the maps between their ids and positions
+    storage: Storage<T>,
+    /// Determines the order of the values
+    compare: C,
+    arena: PhantomData<Arena>,
+}
+
+impl<Arena, T, C: Default> Default for IndexedMinMaxQueue<Arena, T, C> {
+    #[inline]
+    fn default() -> Self {
+        Self::with_comparator(C::default())
+    }
+}
+
+impl<Arena, T: Clone, C: Clone> Clone for IndexedMinMaxQueue<Arena, T, C> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Self {
+            storage: self.storage.clone(),
+            compare: self.compare.clone(),
+            arena: PhantomData,
+        }
+    }
+
+    #[inline]
+    fn clone_from(&mut self, rhs: &Self) {
+        self.storage.clone_from(&rhs.storage);
+        self.compare.clone_from(&rhs.compare);
+    }
+}
+
+impl<Arena, T, C> IndexedMinMaxQueue<Arena, T, C> {
+    #[inline]
+    pub fn with_comparator(compare: C) -> Self {
+        Self {
+            storage: Default::default(),
+            compare,
+            arena: PhantomData,
+        }
+    }
+
+    #[inline]
+    pub fn comparator(&self) -> &C {
+        &self.compare
+    }
+
+    /// Returns true if the slot of `id` holds the entry of another generation.
+    #[inline]
+    pub(crate) fn has_stale_entry(&self, id: impl ValidId<Arena = Arena>) -> bool {
+        let id = id.id().untyped;
+        self.storage.slot_position(id).is_some() && self.storage.position(id).is_none()
+    }
+}
+
+impl<Arena, T, C: Compare<T>> IndexedMinMaxQueue<Arena, T, C> {
+    #[inline]
+    pub fn clear(&mut self) {
+        self.storage.clear();
+        self.check_invariants();
+    }
+
+    #[inline]
+    pub fn insert(&mut self, id: impl ValidId<Arena = Arena>, value: T) {
+        let id = id.id().untyped;
+
+        match self.storage.position(id) {
+            Some(position) => {
+                if let Some(current) = self.storage.get_position_mut(position) {
+                    *current = value;
+                }
+                self.fix(position);
+                self.check_invariants();
+            }
+            None => self.push(id, value),
+        }
+    }
+
+    /// As in [`UntypedIndexedQueue::try_insert`](crate::untyped::UntypedIndexedQueue::try_insert).
+    #[inline]
+    pub fn try_insert(
+        &mut self,
+        id: impl ValidId<Arena = Arena>,
+        value: T,
+    ) -> Result<(), QueueError> {
+        if !self.can_insert(id.id().untyped) {
+            return Err(QueueError::CapacityOverflow);
+        }
+
+        self.insert(id, value);
+        Ok(())
+    }
+
+    #[inline]
+    pub fn remove(&mut self, id: impl ValidId<Arena = Arena>) -> Option<(Id<Arena>, T)> {
+        let position = self.storage.position(id.id().untyped)?;
+        self.remove_position(position)
+    }
+
+    #[inline]
+    pub fn get(&self, id: impl ValidId<Arena = Arena>) -> Option<&T> {
+        self.storage
+            .get_position(self.storage.position(id.id().untyped)?)
+    }
+
+    /// Returns the entry at the front of the queue.
+    #[inline]
+    pub fn peek_min(&self) -> Option<(Id<Arena>, &T)> {
+        self.get_position_with_id(0)
+    }
+
+    /// Returns the entry at the back of the queue.
+    #[inline]
+    pub fn peek_max(&self) -> Option<(Id<Arena>, &T)> {
+        self.get_position_with_id(self.max_position()?)
+    }
+
+    #[inline]
+    pub fn pop_min(&mut self) -> Option<(Id<Arena>, T)> {
+        self.remove_position(0)
+    }
+
+    #[inline]
+    pub fn pop_max(&mut self) -> Option<(Id<Arena>, T)> {
+        let position = self.max_position()?;
+        self.remove_position(position)
+    }
+
+    /// Replaces the value for `id` if `value` is smaller than it.
+    #[inline]
+    pub fn decrease(&mut self, id: impl ValidId<Arena = Arena>, value: T) -> Update<T>
+    where
+        T: Ord,
+    {
+        self.update(id.id().untyped, value, |_, value, current| value < current)
+    }
+
+    /// As in [`UntypedIndexedQueue::try_decrease`](crate::untyped::UntypedIndexedQueue::try_decrease).
+    #[inline]
+    pub fn try_decrease(
+        &mut self,
+        id: impl ValidId<Arena = Arena>,
+        value: T,
+    ) -> Result<T, QueueError>
+    where
+        T: Ord,
+    {
+        self.decrease(id, value).into_result()
+    }
+
+    /// Replaces the value for `id` if `value` is larger than it.
+    #[inline]
+    pub fn increase(&mut self, id: impl ValidId<Arena = Arena>, value: T) -> Update<T>
+    where
+        T: Ord,
+    {
+        self.update(id.id().untyped, value, |_, value, current| value > current)
+    }
+
+    /// Moves the entry for `id` towards the front of the queue if `value` is ordered before its current value.
+    #[inline]
+    pub fn promote(&mut self, id: impl ValidId<Arena = Arena>, value: T) -> Update<T> {
+        self.update(id.id().untyped, value, |compare, value, current| {
+            compare.compare(value, current) == Ordering::Less
+        })
+    }
+
+    /// Moves the entry for `id` towards the back of the queue if `value` is ordered after its current value.
+    #[inline]
+    pub fn demote(&mut self, id: impl ValidId<Arena = Arena>, value: T) -> Update<T> {
+        self.update(id.id().untyped, value, |compare, value, current| {
+            compare.compare(value, current) == Ordering::Greater
+        })
+    }
+
+    /// Inserts the entry if `id` is absent, otherwise decreases it. Returns true if the queue changed.
+    #[inline]
+    pub fn push_or_decrease(&mut self, id: impl ValidId<Arena = Arena>, value: T) -> bool
+    where
+        T: Ord,
+    {
+        self.push_or_update(id.id().untyped, value, |_, value, current| value < current)
+    }
+
+    /// Inserts the entry if `id` is absent, otherwise promotes it. Returns true if the queue changed.
+    #[inline]
+    pub fn push_or_promote(&mut self, id: impl ValidId<Arena = Arena>, value: T) -> bool {
+        self.push_or_update(id.id().untyped, value, |compare, value, current| {
+            compare.compare(value, current) == Ordering::Less
+        })
+    }
+
+    #[inline]
+    fn push_or_update(
+        &mut self,
+        id: UntypedId,
+        value: T,
+        accept: impl FnOnce(&C, &T, &T) -> bool,
+    ) -> bool {
+        match self.storage.position(id) {
+            Some(_) => self.update(id, value, accept).is_updated(),
+            None => {
+                self.push(id, value);
+                true
+            }
+        }
+    }
+
+    /// Removes the entries whose ids are no longer alive in `allocator`.
+    #[inline]
+    pub fn retain_live(&mut self, allocator: &Allocator<Arena>) {
+        self.retain(|id, _| allocator.is_alive(id));
+    }
+
+    /// Removes the entries for which `f` returns false, restoring the heap order once in O(n).
+    #[inline]
+    pub fn retain(&mut self, mut f: impl FnMut(Id<Arena>, &T) -> bool) {
+        self.retain_mut(|id, value| f(id, value));
+    }
+
+    /// Removes the entries for which `f` returns false and keeps any changes `f` makes to the
+    /// others, restoring the heap order once in O(n).
+    #[inline]
+    pub fn retain_mut(&mut self, mut f: impl FnMut(Id<Arena>, &mut T) -> bool) {
+        Heap::retain_mut(self, |id, value| f(Id::new(id), value));
+    }
+
+    /// Iterates over the entries in storage order. The first entry is the front of the queue and the back is one of the next two.
+    #[inline]
+    pub fn iter_unordered(&self) -> impl Iterator<Item = (Id<Arena>, &T)> {
+        self.storage.iter().map(|(id, value)| (Id::new(*id), value))
+    }
+
+    /// Iterates over the entries in priority order without modifying the queue, sorting a copy of
+    /// the references in O(n log n).
+    #[inline]
+    pub fn iter_sorted(&self) -> impl Iterator<Item = (Id<Arena>, &T)> {
+        let mut entries = self.iter_unordered().collect::<Vec<_>>();
+        entries.sort_by(|(_, a), (_, b)| self.compare.compare(a, b));
+        entries.into_iter()
+    }
+
+    /// Removes the entries in storage order in O(n). Any entries left when the iterator is dropped are removed.
+    #[inline]
+    pub fn drain(&mut self) -> impl Iterator<Item = (Id<Arena>, T)> + '_ {
+        self.storage.drain().map(|(id, value)| (Id::new(id), value))
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.storage.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    #[inline]
+    fn get_position_with_id(&self, position: usize) -> Option<(Id<Arena>, &T)> {
+        let (id, value) = self.storage.get_position_with_id(position)?;
+        Some((Id::new(*id), value))
+    }
+
+    /// Returns the position of the back-most entry, which is the root or one of its children.
+    #[inline]
+    fn max_position(&self) -> Option<usize> {
+        match self.len() {
+            0 => None,
+            1 => Some(0),
+            2 => Some(1),
+            _ => Some(if self.is_less(1, 2) { 2 } else { 1 }),
+        }
+    }
+
+    /// Removes the entry at `position`. The front of the queue is at position 0 and the back at 1 or 2.
+    #[inline]
+    pub fn remove_position(&mut self, position: usize) -> Option<(Id<Arena>, T)> {
+        let (id, value) = Heap::remove_position(self, position)?;
+        Some((Id::new(id), value))
+    }
+
+    /// As in [`UntypedIndexedQueue::try_remove_position`](crate::untyped::UntypedIndexedQueue::try_remove_position).
+    #[inline]
+    pub fn try_remove_position(&mut self, position: usize) -> Result<(Id<Arena>, T), QueueError> {
+        let (id, value) = Heap::try_remove_position(self, position)?;
+        Ok((Id::new(id), value))
+    }
+
+    /// Replaces the value for `id` if `accept(compare, value, current)` returns true.
+    #[inline]
+    fn update(
+        &mut self,
+        id: UntypedId,
+        value: T,
+        accept: impl FnOnce(&C, &T, &T) -> bool,
+    ) -> Update<T> {
+        let position = match self.storage.position(id) {
+            Some(position) => position,
+            None => return Update::Absent,
+        };
+
+        let current = match self.storage.get_position_mut(position) {
+            Some(current) => current,
+            None => return Update::Absent,
+        };
+
+        if accept(&self.compare, &value, current) {
+            let old = std::mem::replace(current, value);
+            self.fix(position);
+            self.check_invariants();
+            Update::Updated { old }
+        } else {
+            Update::NotImproved
+        }
+    }
+
+    /// Moves the value at `index` up through the levels of the same kind, returning its final position.
+    #[inline]
+    fn push_up(&mut self, mut index: usize, min_level: bool) -> usize {
+        while let Some(grandparent) = get_grandparent(index) {
+            if self.is_before(index, grandparent, min_level) {
+                self.storage.swap(index, grandparent);
+                index = grandparent;
+            } else {
+                break;
+            }
+        }
+        index
+    }
+
+    #[inline]
+    fn push_down(&mut self, mut index: usize) {
+        let min_level = is_min_level(index);
+
+        while let Some(next) = self.extreme_descendant(index, min_level) {
+            if !self.is_before(next, index, min_level) {
+                return;
+            }
+
+            self.storage.swap(next, index);
+
+            if get_parent(next, 2) == Some(index) {
+                return;
+            }
+
+            let parent = get_parent(next, 2).unwrap();
+            if self.is_before(parent, next, min_level) {
+                self.storage.swap(next, parent);
+            }
+
+            index = next;
+        }
+    }
+
+    /// Returns the front-most (or back-most, on a max level) of the children and grandchildren of `index`.
+    #[inline]
+    fn extreme_descendant(&self, index: usize, min_level: bool) -> Option<usize> {
+        let len = self.len();
+        get_children(index, len, 2)
+            .chain(get_children(index, len, 2).flat_map(|child| get_children(child, len, 2)))
+            .reduce(|best, next| {
+                if self.is_before(next, best, min_level) {
+                    next
+                } else {
+                    best
+                }
+            })
+    }
+
+    /// Returns true if the value at `a` belongs closer to the root than the value at `b` on a
+    /// min level, or the reverse on a max level.
+    #[inline]
+    fn is_before(&self, a: usize, b: usize, min_level: bool) -> bool {
+        if min_level {
+            self.is_less(a, b)
+        } else {
+            self.is_less(b, a)
+        }
+    }
+
+    #[inline]
+    fn is_less(&self, a: usize, b: usize) -> bool {
+        match (self.storage.get_position(a), self.storage.get_position(b)) {
+            (Some(a), Some(b)) => self.compare.compare(a, b) == Ordering::Less,
+            _ => false,
+        }
+    }
+
+    /// Checks the order of every value against its ancestors and the mapping between ids and
+    /// positions in O(n log n), returning the first broken invariant. Entries that compare equal
+    /// may appear in any order.
+    #[inline]
+    pub fn validate(&self) -> Result<(), InvariantError> {
+        self.storage.validate()?;
+
+        for child in 1..self.len() {
+            let mut ancestor = get_parent(child, 2);
+            while let Some(parent) = ancestor {
+                if self.is_before(child, parent, is_min_level(parent)) {
+                    return Err(InvariantError::HeapOrder { parent, child });
+                }
+                ancestor = get_parent(parent, 2);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<Arena, T, C: Compare<T>> Heap for IndexedMinMaxQueue<Arena, T, C> {
+    type Value = T;
+
+    #[inline]
+    fn storage(&self) -> &Storage<T> {
+        &self.storage
+    }
+
+    #[inline]
+    fn storage_mut(&mut self) -> &mut Storage<T> {
+        &mut self.storage
+    }
+
+    /// Restores the heap order around a value that was placed at `position`.
+    #[inline]
+    fn fix(&mut self, position: usize) {
+        let min_level = is_min_level(position);
+
+        match get_parent(position, 2) {
+            // The value belongs on the parent's level, so the parent's value moves down in its place
+            Some(parent) if self.is_before(parent, position, min_level) => {
+                self.storage.swap(position, parent);
+                self.push_down(position);
+                self.push_up(parent, !min_level);
+            }
+            _ => {
+                if self.push_up(position, min_level) == position {
+                    self.push_down(position);
+                }
+            }
+        }
+    }
+
+    /// Pushes down each parent, starting from the last.
+    #[inline]
+    fn heapify(&mut self) {
+        let last_parent = self
+            .len()
+            .checked_sub(1)
+            .and_then(|last| get_parent(last, 2));
+
+        if let Some(last_parent) = last_parent {
+            for position in (0..=last_parent).rev() {
+                self.push_down(position);
+            }
+        }
+    }
+
+    #[inline]
+    fn check_invariants(&self) {
+        invariant::debug_check(|| self.validate());
+    }
+}
+
+impl<Arena, T, C, V: ValidId<Arena = Arena>> Index<V> for IndexedMinMaxQueue<Arena, T, C> {
+    type Output = Option<T>;
+
+    #[inline]
+    fn index(&self, index: V) -> &Self::Output {
+        &self.storage[index.id().untyped]
+    }
+}
+
+/// Adds every entry, moving each into place while the batch is small next to the queue and
+/// otherwise restoring the heap order bottom-up once.
+///
+/// An id that is already in the queue, or repeated within the iterator, takes the last value given
+/// for it. An entry for an older generation of a queued slot is dropped.
+impl<Arena, T, C: Compare<T>> Extend<(Id<Arena>, T)> for IndexedMinMaxQueue<Arena, T, C> {
+    #[inline]
+    fn extend<I: IntoIterator<Item = (Id<Arena>, T)>>(&mut self, iter: I) {
+        self.extend_with(
+            iter.into_iter().map(|(id, value)| (id.untyped, value)),
+            |_, value| value,
+        );
+    }
+}
+
+impl<Arena, T, C: Compare<T> + Default> FromIterator<(Id<Arena>, T)>
+    for IndexedMinMaxQueue<Arena, T, C>
+{
+    #[inline]
+    fn from_iter<I: IntoIterator<Item = (Id<Arena>, T)>>(iter: I) -> Self {
+        let mut queue = Self::default();
+        queue.extend(iter);
+        queue
+    }
+}
+
+/// Returns true if `index` is on an even level of the heap, counting the root as level 0.
+#[inline]
+fn is_min_level(index: usize) -> bool {
+    let level = usize::BITS - 1 - (index + 1).leading_zeros();
+    level % 2 == 0
+}
+
+#[inline]
+fn get_grandparent(index: usize) -> Option<usize> {
+    get_parent(index, 2).and_then(|parent| get_parent(parent, 2))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{get_id, Arena};
+    use crate::Max;
+    use gen_id_allocator::untyped::UntypedAllocator;
+    use rand::{thread_rng, Rng};
+
+    fn new_queue(values: &[u32]) -> IndexedMinMaxQueue<Arena, u32> {
+        let mut queue = IndexedMinMaxQueue::default();
+        for (i, value) in values.iter().enumerate() {
+            queue.insert(get_id(i), *value);
+        }
+        queue
+    }
+
+    #[test]
+    fn min_levels() {
+        let levels = (0..8).map(is_min_level).collect::<Vec<_>>();
+
+        assert_eq!(
+            vec![true, false, false, true, true, true, true, false],
+            levels
+        );
+    }
+
+    #[test]
+    fn peek_both_ends() {
+        let queue = new_queue(&[5, 1, 9, 3, 7]);
+
+        queue.validate().unwrap();
+        assert_eq!(Some((get_id(1), &1)), queue.peek_min());
+        assert_eq!(Some((get_id(2), &9)), queue.peek_max());
+    }
+
+    #[test]
+    fn peek_single_entry() {
+        let queue = new_queue(&[4]);
+
+        assert_eq!(queue.peek_min(), queue.peek_max());
+        assert_eq!(None, new_queue(&[]).peek_max());
+    }
+
+    #[test]
+    fn pop_from_both_ends() {
+        let mut queue = new_queue(&[5, 1, 9, 3, 7, 2, 8]);
+
+        assert_eq!(Some((get_id(1), 1)), queue.pop_min());
+        assert_eq!(Some((get_id(2), 9)), queue.pop_max());
+        assert_eq!(Some((get_id(5), 2)), queue.pop_min());
+        assert_eq!(Some((get_id(6), 8)), queue.pop_max());
+        queue.validate().unwrap();
+        assert_eq!(3, queue.len());
+    }
+
+    #[test]
+    fn pop_empty_returns_none() {
+        let mut queue = new_queue(&[]);
+
+        assert_eq!(None, queue.pop_min());
+        assert_eq!(None, queue.pop_max());
+    }
+
+    #[test]
+    fn remove_and_index() {
+        let mut queue = new_queue(&[5, 1, 9, 3]);
+
+        assert_eq!(Some((get_id(0), 5)), queue.remove(get_id(0)));
+        assert_eq!(None, queue.remove(get_id(0)));
+        assert_eq!(None, queue[get_id(0)]);
+        assert_eq!(Some(3), queue[get_id(3)]);
+        queue.validate().unwrap();
+    }
+
+    #[test]
+    fn decrease_and_increase() {
+        let mut queue = new_queue(&[5, 1, 9, 3]);
+
+        assert_eq!(Update::Updated { old: 3 }, queue.decrease(get_id(3), 0));
+        assert_eq!(Update::NotImproved, queue.decrease(get_id(2), 10));
+        assert_eq!(Update::Updated { old: 5 }, queue.increase(get_id(0), 10));
+        assert_eq!(Update::Absent, queue.increase(get_id(4), 10));
+
+        assert_eq!(Some((get_id(3), &0)), queue.peek_min());
+        assert_eq!(Some((get_id(0), &10)), queue.peek_max());
+        queue.validate().unwrap();
+    }
+
+    #[test]
+    fn promote_and_demote_follow_the_comparator() {
+        let mut queue = IndexedMinMaxQueue::<Arena, u32, Max>::default();
+        queue.insert(get_id(0), 1);
+        queue.insert(get_id(1), 3);
+        queue.insert(get_id(2), 2);
+
+        assert_eq!(Update::Updated { old: 1 }, queue.promote(get_id(0), 4));
+        assert_eq!(Update::NotImproved, queue.demote(get_id(1), 5));
+        assert_eq!(Update::Updated { old: 3 }, queue.decrease(get_id(1), 0));
+
+        assert_eq!(Some((get_id(0), &4)), queue.peek_min());
+        assert_eq!(Some((get_id(1), &0)), queue.peek_max());
+        queue.validate().unwrap();
+    }
+
+    #[test]
+    fn max_comparator_swaps_ends() {
+        let mut queue = IndexedMinMaxQueue::<Arena, u32, Max>::default();
+        queue.insert(get_id(0), 1);
+        queue.insert(get_id(1), 3);
+        queue.insert(get_id(2), 2);
+
+        assert_eq!(Some((get_id(1), &3)), queue.peek_min());
+        assert_eq!(Some((get_id(0), &1)), queue.peek_max());
+    }
+
+    #[test]
+    fn validate_accepts_duplicate_priorities() {
+        let mut queue = new_queue(&[2, 1, 2, 1, 1, 2, 2, 1, 1, 2, 1, 2]);
+        assert_eq!(Ok(()), queue.validate());
+
+        queue.pop_max();
+        queue.pop_min();
+        queue.decrease(get_id(5), 1);
+
+        assert_eq!(Ok(()), queue.validate());
+    }
+
+    #[test]
+    fn validate_detects_heap_order() {
+        let mut queue = new_queue(&[1, 5, 3]);
+        queue
+            .storage
+            .values
+            .swap(queue.storage.inverse_map[0], queue.storage.inverse_map[1]);
+
+        assert_eq!(
+            Err(InvariantError::HeapOrder {
+                parent: 0,
+                child: 1
+            }),
+            queue.validate()
+        );
+    }
+
+    #[test]
+    fn iter_sorted_follows_the_comparator() {
+        let queue = new_queue(&[5, 1, 9, 3, 7]);
+
+        let values = queue.iter_sorted().map(|(_, v)| *v).collect::<Vec<_>>();
+
+        assert_eq!(vec![1, 3, 5, 7, 9], values);
+        assert_eq!(5, queue.len());
+    }
+
+    #[test]
+    fn retain_restores_order() {
+        let mut queue = new_queue(&[5, 1, 9, 3, 7, 2, 8, 6]);
+
+        queue.retain(|_, value| value % 3 != 0);
+
+        queue.validate().unwrap();
+        assert_eq!(Ok(()), queue.validate());
+        assert_eq!(Some((get_id(1), &1)), queue.peek_min());
+        assert_eq!(Some((get_id(6), &8)), queue.peek_max());
+        assert_eq!(5, queue.len());
+    }
+
+    #[test]
+    fn retain_live_removes_dead_ids() {
+        let mut alloc = Allocator::<Arena>::default();
+        let ids = (0..4).map(|_| alloc.create()).collect::<Vec<_>>();
+        let mut queue = ids
+            .iter()
+            .zip([4, 3, 2, 1])
+            .map(|(id, value)| (*id, value))
+            .collect::<IndexedMinMaxQueue<Arena, u32>>();
+
+        alloc.kill(ids[3]);
+        queue.retain_live(&alloc);
+
+        assert_eq!(3, queue.len());
+        assert_eq!(Some((ids[2], &2)), queue.peek_min());
+        assert_eq!(Some((ids[0], &4)), queue.peek_max());
+    }
+
+    #[test]
+    fn extend_takes_the_last_value() {
+        let mut queue = new_queue(&[5, 1]);
+
+        queue.extend([(get_id(2), 9), (get_id(0), 0), (get_id(2), 3)]);
+
+        queue.validate().unwrap();
+        assert_eq!(3, queue.len());
+        assert_eq!(Some((get_id(0), &0)), queue.peek_min());
+        assert_eq!(Some((get_id(2), &3)), queue.peek_max());
+    }
+
+    #[test]
+    fn extend_with_a_large_batch_rebuilds() {
+        let mut queue = new_queue(&[50]);
+
+        queue.extend((1..100).map(|i| (get_id(i), (i as u32 * 37) % 101)));
+
+        queue.validate().unwrap();
+        assert_eq!(Ok(()), queue.validate());
+        assert_eq!(100, queue.len());
+    }
+
+    #[test]
+    fn drain_empties_the_queue() {
+        let mut queue = new_queue(&[5, 1, 9]);
+
+        let mut drained = queue.drain().map(|(_, v)| v).collect::<Vec<_>>();
+        drained.sort_unstable();
+
+        assert_eq!(vec![1, 5, 9], drained);
+        assert!(queue.is_empty());
+        assert_eq!(None, queue[get_id(0)]);
+        assert_eq!(Ok(()), queue.validate());
+    }
+
+    #[test]
+    fn push_or_decrease() {
+        let mut queue = new_queue(&[5, 1]);
+
+        assert!(queue.push_or_decrease(get_id(2), 9));
+        assert!(queue.push_or_decrease(get_id(0), 0));
+        assert!(!queue.push_or_decrease(get_id(1), 2));
+
+        assert_eq!(Some((get_id(0), &0)), queue.peek_min());
+        assert_eq!(Some((get_id(2), &9)), queue.peek_max());
+        queue.validate().unwrap();
+    }
+
+    #[test]
+    fn procedural_test() {
+        let rng = &mut thread_rng();
+        let mut alloc = UntypedAllocator::default();
+        let mut queue = IndexedMinMaxQueue::<Arena, u32>::default();
+        let mut live: Vec<UntypedId> = vec![];
+
+        for _ in 0..2000 {
+            match rng.gen_range(0, 6) {
+                0 | 1 => {
+                    let id = alloc.create();
+                    live.push(id);
+                    queue.insert(Id::new(id), rng.gen_range(0, 1000));
+                }
+                2 if !live.is_empty() => {
+                    let id = live[rng.gen_range(0, live.len())];
+                    queue.insert(Id::new(id), rng.gen_range(0, 1000));
+                }
+                3 if !live.is_empty() => {
+                    let id = live.swap_remove(rng.gen_range(0, live.len()));
+                    assert!(queue.remove(Id::new(id)).is_some());
+                    alloc.kill(id);
+                }
+                4 => {
+                    let min = queue.iter_unordered().map(|(_, v)| *v).min();
+                    assert_eq!(min, queue.pop_min().map(|(_, v)| v));
+                    live.retain(|id| queue.get(Id::<Arena>::new(*id)).is_some());
+                }
+                _ => {
+                    let max = queue.iter_unordered().map(|(_, v)| *v).max();
+                    assert_eq!(max, queue.pop_max().map(|(_, v)| v));
+                    live.retain(|id| queue.get(Id::<Arena>::new(*id)).is_some());
+                }
+            }
+            queue.validate().unwrap();
+            assert_eq!(live.len(), queue.len());
+        }
+    }
+
+    #[test]
+    fn try_methods_report_errors() {
+        let mut queue = new_queue(&[5, 1]);
+
+        assert_eq!(Ok(5), queue.try_decrease(get_id(0), 3));
+        assert_eq!(
+            Err(QueueError::OutOfBounds {
+                position: 2,
+                len: 2
+            }),
+            queue.try_remove_position(2)
+        );
+    }
+}
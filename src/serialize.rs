@@ -0,0 +1,119 @@
+use crate::compare::Compare;
+use crate::untyped::UntypedIndexedQueue;
+use crate::IndexedQueue;
+use gen_id_allocator::untyped::UntypedId;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::marker::PhantomData;
+
+/// Serializes the entries as a sequence of `(id, value)` pairs in heap order.
+impl<T: Serialize, C: Compare<T>, const D: usize> Serialize for UntypedIndexedQueue<T, C, D> {
+    #[inline]
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter_unordered())
+    }
+}
+
+/// Rebuilds the position map from the serialized entries, rejecting any two that share a slot,
+/// and re-heapifies in case the entries are not in heap order.
+impl<'de, T: Deserialize<'de>, C: Compare<T> + Default, const D: usize> Deserialize<'de>
+    for UntypedIndexedQueue<T, C, D>
+{
+    #[inline]
+    fn deserialize<De: Deserializer<'de>>(deserializer: De) -> Result<Self, De::Error> {
+        let entries = Vec::<(UntypedId, T)>::deserialize(deserializer)?;
+
+        Self::try_from_entries(entries, C::default()).map_err(|id| {
+            De::Error::custom(format!(
+                "corrupt queue: more than one entry for slot {} (last seen as {:?})",
+                id.index(),
+                id
+            ))
+        })
+    }
+}
+
+impl<Arena, T: Serialize, C: Compare<T>, const D: usize> Serialize
+    for IndexedQueue<Arena, T, C, D>
+{
+    #[inline]
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.inner.serialize(serializer)
+    }
+}
+
+impl<'de, Arena, T: Deserialize<'de>, C: Compare<T> + Default, const D: usize> Deserialize<'de>
+    for IndexedQueue<Arena, T, C, D>
+{
+    #[inline]
+    fn deserialize<De: Deserializer<'de>>(deserializer: De) -> Result<Self, De::Error> {
+        Ok(Self {
+            inner: UntypedIndexedQueue::deserialize(deserializer)?,
+            arena: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_util::{get_id, Arena};
+    use crate::untyped::UntypedIndexedMinQueue;
+    use crate::{IndexedMaxQueue, IndexedMinQueue};
+
+    #[test]
+    fn round_trip_min_queue() {
+        let queue = (0..20)
+            .map(|i| (get_id(i), (i as u32 * 7) % 20))
+            .collect::<IndexedMinQueue<Arena, u32>>();
+
+        let json = serde_json::to_string(&queue).unwrap();
+        let mut loaded: IndexedMinQueue<Arena, u32> = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(queue.len(), loaded.len());
+        for (id, value) in queue.iter_unordered() {
+            assert_eq!(Some(*value), loaded[id]);
+        }
+        assert_eq!(Some((get_id(0), 0)), loaded.pop());
+    }
+
+    #[test]
+    fn round_trip_max_queue() {
+        let queue = (0..5)
+            .map(|i| (get_id(i), i as u32))
+            .collect::<IndexedMaxQueue<Arena, u32>>();
+
+        let json = serde_json::to_string(&queue).unwrap();
+        let loaded: IndexedMaxQueue<Arena, u32> = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(
+            queue.iter_sorted().collect::<Vec<_>>(),
+            loaded.iter_sorted().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn entries_out_of_heap_order_are_reheapified() {
+        let entries = vec![
+            (UntypedId::first(0), 3u32),
+            (UntypedId::first(1), 1),
+            (UntypedId::first(2), 2),
+        ];
+        let json = serde_json::to_string(&entries).unwrap();
+
+        let mut queue: UntypedIndexedMinQueue<u32> = serde_json::from_str(&json).unwrap();
+
+        queue.validate().unwrap();
+        assert_eq!(Some((UntypedId::first(1), 1)), queue.remove_position(0));
+    }
+
+    #[test]
+    fn duplicate_slots_are_rejected() {
+        let entries = vec![(UntypedId::first(0), 3u32), (UntypedId::first(0), 1)];
+        let json = serde_json::to_string(&entries).unwrap();
+
+        let error = serde_json::from_str::<UntypedIndexedMinQueue<u32>>(&json).unwrap_err();
+
+        assert!(error.to_string().contains("more than one entry for slot 0"));
+    }
+}
@@ -0,0 +1,433 @@
+use crate::compare::Compare;
+use crate::float::{Float, NanPolicy, TotalOrd};
+use crate::untyped::UntypedIndexedQueue;
+use crate::IndexedQueue;
+use gen_id_allocator::untyped::UntypedId;
+use std::convert::TryFrom;
+use std::fmt::{Display, Formatter};
+use std::io::{self, Read, Write};
+use std::marker::PhantomData;
+
+/// Identifies a queue snapshot.
+const MAGIC: [u8; 4] = *b"GIPQ";
+
+/// The snapshot format version that is written and the only one that can be read.
+pub const SNAPSHOT_VERSION: u16 = 2;
+
+/// The length of the header fields that follow the header length: arity `u16` and entry count `u64`.
+const HEADER_LEN: u16 = 10;
+
+/// The length of the entry fields before the value: slot index `u32` and generation `u32`.
+const ENTRY_ID_LEN: usize = 8;
+
+/// A value that can be written to and read from a queue snapshot.
+pub trait SnapshotValue: Sized {
+    fn write_to<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<()>;
+
+    fn read_from<R: Read + ?Sized>(reader: &mut R) -> io::Result<Self>;
+}
+
+macro_rules! snapshot_value {
+    ($($ty:ty),*) => {
+        $(
+            impl SnapshotValue for $ty {
+                #[inline]
+                fn write_to<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
+                    writer.write_all(&self.to_le_bytes())
+                }
+
+                #[inline]
+                fn read_from<R: Read + ?Sized>(reader: &mut R) -> io::Result<Self> {
+                    let mut bytes = [0; std::mem::size_of::<$ty>()];
+                    reader.read_exact(&mut bytes)?;
+                    Ok(<$ty>::from_le_bytes(bytes))
+                }
+            }
+        )*
+    };
+}
+
+snapshot_value!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, f32, f64);
+
+impl<F: Float + SnapshotValue, P: NanPolicy> SnapshotValue for TotalOrd<F, P> {
+    #[inline]
+    fn write_to<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
+        self.get().write_to(writer)
+    }
+
+    #[inline]
+    fn read_from<R: Read + ?Sized>(reader: &mut R) -> io::Result<Self> {
+        TotalOrd::new(F::read_from(reader)?)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "NaN priority"))
+    }
+}
+
+/// The reasons a snapshot can fail to load.
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    /// The input does not start with the snapshot header.
+    BadMagic,
+    /// The snapshot has a format version other than [`SNAPSHOT_VERSION`].
+    UnsupportedVersion(u16),
+    /// Two entries share the slot of this id.
+    DuplicateSlot(UntypedId),
+}
+
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SnapshotError::Io(error) => write!(f, "failed to read snapshot: {}", error),
+            SnapshotError::BadMagic => write!(f, "input is not a queue snapshot"),
+            SnapshotError::UnsupportedVersion(version) => {
+                write!(f, "snapshot version {} is not supported", version)
+            }
+            SnapshotError::DuplicateSlot(id) => {
+                write!(
+                    f,
+                    "corrupt snapshot: more than one entry for slot {}",
+                    id.index()
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SnapshotError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    #[inline]
+    fn from(error: io::Error) -> Self {
+        SnapshotError::Io(error)
+    }
+}
+
+impl<T: SnapshotValue, C: Compare<T>, const D: usize> UntypedIndexedQueue<T, C, D> {
+    /// Writes the queue as a little-endian snapshot:
+    ///
+    /// - header: `b"GIPQ"`, format version `u16`, header length `u16`, arity `u16`, entry count `u64`
+    /// - entries in heap order: entry length `u32`, slot index `u32`, generation `u32`, value
+    ///
+    /// Fails with [`io::ErrorKind::InvalidInput`] before writing anything if the arity does not fit
+    /// in a `u16` or a slot index does not fit in a `u32`.
+    #[inline]
+    pub fn write_snapshot<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
+        let arity = u16::try_from(D).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "arity does not fit in a snapshot",
+            )
+        })?;
+        for (id, _) in self.iter_unordered() {
+            snapshot_index(*id)?;
+        }
+
+        writer.write_all(&MAGIC)?;
+        SNAPSHOT_VERSION.write_to(writer)?;
+        HEADER_LEN.write_to(writer)?;
+        arity.write_to(writer)?;
+        (self.len() as u64).write_to(writer)?;
+
+        let mut value_bytes = vec![];
+        for (id, value) in self.iter_unordered() {
+            value_bytes.clear();
+            value.write_to(&mut value_bytes)?;
+
+            let entry_len = u32::try_from(ENTRY_ID_LEN + value_bytes.len()).map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidInput, "value is too long to snapshot")
+            })?;
+
+            entry_len.write_to(writer)?;
+            snapshot_index(*id)?.write_to(writer)?;
+            id.gen().write_to(writer)?;
+            writer.write_all(&value_bytes)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads a snapshot written at any arity, restoring the heap order for `D`.
+    #[inline]
+    pub fn read_snapshot<R: Read>(mut reader: R) -> Result<Self, SnapshotError>
+    where
+        C: Default,
+    {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(SnapshotError::BadMagic);
+        }
+
+        let version = u16::read_from(&mut reader)?;
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        let header_len = u16::read_from(&mut reader)?;
+        let mut header = (&mut reader).take(header_len.into());
+        let len = read_header(&mut header)?;
+        // Skip any trailing header fields
+        io::copy(&mut header, &mut io::sink())?;
+
+        // The count is untrusted, so only use it as a hint
+        let mut entries = Vec::with_capacity(len.min(1 << 16) as usize);
+        for _ in 0..len {
+            let entry_len = u32::read_from(&mut reader)?;
+            let mut fields = (&mut reader).take(entry_len.into());
+            entries.push(read_entry(&mut fields)?);
+            // Skip any trailing entry fields
+            io::copy(&mut fields, &mut io::sink())?;
+        }
+
+        Self::try_from_entries(entries, C::default()).map_err(SnapshotError::DuplicateSlot)
+    }
+}
+
+impl<Arena, T: SnapshotValue, C: Compare<T>, const D: usize> IndexedQueue<Arena, T, C, D> {
+    /// Writes the queue in the format of [`UntypedIndexedQueue::write_snapshot`].
+    #[inline]
+    pub fn write_snapshot<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<()> {
+        self.inner.write_snapshot(writer)
+    }
+
+    #[inline]
+    pub fn read_snapshot<R: Read>(reader: R) -> Result<Self, SnapshotError>
+    where
+        C: Default,
+    {
+        Ok(Self {
+            inner: UntypedIndexedQueue::read_snapshot(reader)?,
+            arena: PhantomData,
+        })
+    }
+}
+
+/// Returns the slot index of `id` as written to a snapshot.
+#[inline]
+fn snapshot_index(id: UntypedId) -> io::Result<u32> {
+    u32::try_from(id.index()).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "slot index does not fit in a snapshot",
+        )
+    })
+}
+
+/// Reads the arity and entry count, returning the count.
+#[inline]
+fn read_header<R: Read + ?Sized>(reader: &mut R) -> io::Result<u64> {
+    let _arity = u16::read_from(reader)?;
+    u64::read_from(reader)
+}
+
+#[inline]
+fn read_entry<T: SnapshotValue, R: Read + ?Sized>(reader: &mut R) -> io::Result<(UntypedId, T)> {
+    let index = u32::read_from(reader)?;
+    let gen = u32::read_from(reader)?;
+    let value = T::read_from(reader)?;
+    Ok((UntypedId::new(index as usize, gen), value))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::untyped::{UntypedIndexedMaxQueue, UntypedIndexedMinQueue};
+    use gen_id_allocator::untyped::UntypedAllocator;
+
+    fn new_queue() -> UntypedIndexedMinQueue<u32> {
+        let mut alloc = UntypedAllocator::default();
+        let stale = alloc.create();
+        alloc.kill(stale);
+
+        (0..20).map(|i| (alloc.create(), (i * 7) % 20)).collect()
+    }
+
+    fn snapshot<T: SnapshotValue, C: Compare<T>, const D: usize>(
+        queue: &UntypedIndexedQueue<T, C, D>,
+    ) -> Vec<u8> {
+        let mut bytes = vec![];
+        queue.write_snapshot(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn round_trip() {
+        let queue = new_queue();
+
+        let loaded = UntypedIndexedMinQueue::<u32>::read_snapshot(&snapshot(&queue)[..]).unwrap();
+
+        loaded.validate().unwrap();
+        assert_eq!(queue.len(), loaded.len());
+        for (id, value) in queue.iter_unordered() {
+            assert_eq!(Some(value), loaded.get(*id));
+        }
+    }
+
+    #[test]
+    fn header_layout() {
+        let bytes = snapshot(&new_queue());
+
+        assert_eq!(b"GIPQ", &bytes[..4]);
+        assert_eq!([2, 0], bytes[4..6]);
+        assert_eq!([10, 0], bytes[6..8]);
+        assert_eq!([8, 0], bytes[8..10]);
+        assert_eq!(20u64.to_le_bytes(), bytes[10..18]);
+        assert_eq!(12u32.to_le_bytes(), bytes[18..22]);
+        assert_eq!(18 + 20 * 16, bytes.len());
+    }
+
+    #[test]
+    fn read_at_another_arity() {
+        let queue = new_queue();
+
+        let loaded =
+            UntypedIndexedMinQueue::<u32, 2>::read_snapshot(&snapshot(&queue)[..]).unwrap();
+
+        loaded.validate().unwrap();
+        assert_eq!(queue.len(), loaded.len());
+    }
+
+    #[test]
+    fn read_with_another_comparator() {
+        let loaded =
+            UntypedIndexedMaxQueue::<u32>::read_snapshot(&snapshot(&new_queue())[..]).unwrap();
+
+        loaded.validate().unwrap();
+        assert_eq!(Some(&19), loaded.get_position(0));
+    }
+
+    #[test]
+    fn reject_bad_magic() {
+        let mut bytes = snapshot(&new_queue());
+        bytes[0] = b'X';
+
+        let error = UntypedIndexedMinQueue::<u32>::read_snapshot(&bytes[..]).unwrap_err();
+
+        assert!(matches!(error, SnapshotError::BadMagic));
+    }
+
+    /// Writes `entries` of `u32` values with the given header fields and per-entry fields.
+    fn raw_snapshot(version: u16, header: &[u8], entries: &[(u32, u32, u32, &[u8])]) -> Vec<u8> {
+        let mut bytes = b"GIPQ".to_vec();
+        bytes.extend_from_slice(&version.to_le_bytes());
+        bytes.extend_from_slice(&(header.len() as u16 + 10).to_le_bytes());
+        bytes.extend_from_slice(&8u16.to_le_bytes());
+        bytes.extend_from_slice(&(entries.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(header);
+
+        for (index, gen, value, extra) in entries {
+            bytes.extend_from_slice(&(12 + extra.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&index.to_le_bytes());
+            bytes.extend_from_slice(&gen.to_le_bytes());
+            bytes.extend_from_slice(&value.to_le_bytes());
+            bytes.extend_from_slice(extra);
+        }
+
+        bytes
+    }
+
+    #[test]
+    fn read_skips_trailing_fields() {
+        let bytes = raw_snapshot(2, &[9, 9], &[(0, 1, 5, &[7]), (1, 0, 3, &[])]);
+
+        let loaded = UntypedIndexedMinQueue::<u32>::read_snapshot(&bytes[..]).unwrap();
+
+        assert_eq!(2, loaded.len());
+        assert_eq!(Some(&5), loaded.get(UntypedId::new(0, 1)));
+        assert_eq!(Some(&3), loaded.get(UntypedId::first(1)));
+    }
+
+    #[test]
+    fn reject_other_versions() {
+        for version in [0, 1, 3] {
+            let bytes = raw_snapshot(version, &[], &[(0, 0, 5, &[])]);
+
+            let error = UntypedIndexedMinQueue::<u32>::read_snapshot(&bytes[..]).unwrap_err();
+
+            assert!(matches!(error, SnapshotError::UnsupportedVersion(v) if v == version));
+        }
+    }
+
+    #[test]
+    fn reject_entry_shorter_than_its_value() {
+        let mut bytes = raw_snapshot(2, &[], &[(0, 0, 5, &[])]);
+        bytes[18..22].copy_from_slice(&10u32.to_le_bytes());
+
+        let error = UntypedIndexedMinQueue::<u32>::read_snapshot(&bytes[..]).unwrap_err();
+
+        assert!(matches!(error, SnapshotError::Io(_)));
+    }
+
+    #[test]
+    fn slot_index_past_u32_is_rejected() {
+        assert_eq!(Some(7), snapshot_index(UntypedId::first(7)).ok());
+
+        let error = snapshot_index(UntypedId::first(u32::MAX as usize + 1)).unwrap_err();
+
+        assert_eq!(io::ErrorKind::InvalidInput, error.kind());
+    }
+
+    #[test]
+    fn arity_past_u16_is_rejected() {
+        let mut queue = UntypedIndexedMinQueue::<u32, 70_000>::default();
+        queue.insert(UntypedId::first(0), 1);
+        let mut bytes = vec![];
+
+        let error = queue.write_snapshot(&mut bytes).unwrap_err();
+
+        assert_eq!(io::ErrorKind::InvalidInput, error.kind());
+        assert!(bytes.is_empty());
+    }
+
+    #[test]
+    fn reject_truncated_input() {
+        let bytes = snapshot(&new_queue());
+
+        let error =
+            UntypedIndexedMinQueue::<u32>::read_snapshot(&bytes[..bytes.len() - 1]).unwrap_err();
+
+        assert!(matches!(error, SnapshotError::Io(_)));
+    }
+
+    #[test]
+    fn reject_duplicate_slots() {
+        let mut queue = UntypedIndexedMinQueue::<u32>::default();
+        queue.insert(UntypedId::first(0), 1);
+        queue.insert(UntypedId::first(1), 2);
+        let mut bytes = snapshot(&queue);
+        // Point the second entry at the first entry's slot
+        bytes[38..42].copy_from_slice(&0u32.to_le_bytes());
+
+        let error = UntypedIndexedMinQueue::<u32>::read_snapshot(&bytes[..]).unwrap_err();
+
+        assert!(matches!(error, SnapshotError::DuplicateSlot(_)));
+        assert_eq!(
+            "corrupt snapshot: more than one entry for slot 0",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn total_ord_values_reject_nan() {
+        let mut queue = UntypedIndexedMinQueue::<TotalOrd<f32>>::default();
+        queue.insert(UntypedId::first(0), TotalOrd::new(1.5).unwrap());
+        let mut bytes = snapshot(&queue);
+        let loaded = UntypedIndexedMinQueue::<TotalOrd<f32>>::read_snapshot(&bytes[..]).unwrap();
+        assert_eq!(Some(1.5), loaded.get(UntypedId::first(0)).map(|v| v.get()));
+
+        bytes[30..34].copy_from_slice(&f32::NAN.to_le_bytes());
+
+        assert!(UntypedIndexedMinQueue::<TotalOrd<f32>, 8>::read_snapshot(&bytes[..]).is_ok());
+        let error =
+            UntypedIndexedMinQueue::<TotalOrd<f32, crate::Reject>>::read_snapshot(&bytes[..])
+                .unwrap_err();
+        assert!(matches!(error, SnapshotError::Io(_)));
+    }
+}
@@ -0,0 +1,466 @@
+use crate::compare::{Compare, Max, Min};
+use crate::error::QueueError;
+use crate::invariant::InvariantError;
+use crate::storage::Heap;
+use crate::untyped::UntypedIndexedQueue;
+use crate::update::Update;
+use gen_id_allocator::untyped::UntypedId;
+use gen_id_allocator::{Allocator, Id, ValidId};
+use std::cmp::Ordering;
+use std::marker::PhantomData;
+
+/// A priority paired with the order in which it was assigned.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Sequenced<T> {
+    value: T,
+    sequence: u64,
+}
+
+/// Orders by the inner comparator and then by sequence, breaking ties in favour of the older entry.
+#[derive(Debug, Default, Copy, Clone)]
+struct Sequential<C>(C);
+
+impl<T, C: Compare<T>> Compare<Sequenced<T>> for Sequential<C> {
+    #[inline]
+    fn compare(&self, a: &Sequenced<T>, b: &Sequenced<T>) -> Ordering {
+        self.0
+            .compare(&a.value, &b.value)
+            .then(a.sequence.cmp(&b.sequence))
+    }
+}
+
+/// An Id-indexed min priority queue that pops equal priorities in first-in, first-out order.
+pub type StableIndexedMinQueue<Arena, T, const D: usize = 8> = StableIndexedQueue<Arena, T, Min, D>;
+
+/// An Id-indexed max priority queue that pops equal priorities in first-in, first-out order.
+pub type StableIndexedMaxQueue<Arena, T, const D: usize = 8> = StableIndexedQueue<Arena, T, Max, D>;
+
+/// An Id-indexed priority queue that pops equal priorities in first-in, first-out order.
+///
+/// Every `insert`, and every `decrease`, `increase`, `promote` or `demote` that changes a priority, takes a new
+/// sequence number, so the entry is placed behind any entries that already share its priority.
+#[derive(Debug)]
+pub struct StableIndexedQueue<Arena, T, C = Min, const D: usize = 8> {
+    inner: UntypedIndexedQueue<Sequenced<T>, Sequential<C>, D>,
+    sequence: u64,
+    arena: PhantomData<Arena>,
+}
+
+impl<Arena, T, C: Default, const D: usize> Default for StableIndexedQueue<Arena, T, C, D> {
+    #[inline]
+    fn default() -> Self {
+        Self::with_comparator(C::default())
+    }
+}
+
+impl<Arena, T: Clone, C: Clone, const D: usize> Clone for StableIndexedQueue<Arena, T, C, D> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            sequence: self.sequence,
+            arena: PhantomData,
+        }
+    }
+
+    #[inline]
+    fn clone_from(&mut self, rhs: &Self) {
+        self.inner.clone_from(&rhs.inner);
+        self.sequence = rhs.sequence;
+    }
+}
+
+impl<Arena, T, C, const D: usize> StableIndexedQueue<Arena, T, C, D> {
+    #[inline]
+    pub fn with_comparator(compare: C) -> Self {
+        Self {
+            inner: UntypedIndexedQueue::with_comparator(Sequential(compare)),
+            sequence: 0,
+            arena: PhantomData,
+        }
+    }
+
+    #[inline]
+    pub fn comparator(&self) -> &C {
+        &self.inner.comparator().0
+    }
+}
+
+impl<Arena, T, C: Compare<T>, const D: usize> StableIndexedQueue<Arena, T, C, D> {
+    #[inline]
+    pub fn clear(&mut self) {
+        self.inner.clear();
+        self.sequence = 0;
+    }
+
+    #[inline]
+    pub fn insert(&mut self, id: impl ValidId<Arena = Arena>, value: T) {
+        let value = self.sequenced(value);
+        self.inner.insert(id.id().untyped, value);
+    }
+
+    /// As in [`UntypedIndexedQueue::try_insert`].
+    #[inline]
+    pub fn try_insert(
+        &mut self,
+        id: impl ValidId<Arena = Arena>,
+        value: T,
+    ) -> Result<(), QueueError> {
+        let id = id.id().untyped;
+
+        // Check first, so that a failed insert does not take a sequence number
+        if !self.inner.can_insert(id) {
+            return Err(QueueError::CapacityOverflow);
+        }
+
+        let value = self.sequenced(value);
+        self.inner.insert(id, value);
+        Ok(())
+    }
+
+    #[inline]
+    pub fn remove(&mut self, id: impl ValidId<Arena = Arena>) -> Option<(Id<Arena>, T)> {
+        self.inner
+            .remove(id.id().untyped)
+            .map(|(id, value)| (Id::new(id), value.value))
+    }
+
+    #[inline]
+    pub fn get(&self, id: impl ValidId<Arena = Arena>) -> Option<&T> {
+        self.inner.get(id.id().untyped).map(|value| &value.value)
+    }
+
+    #[inline]
+    pub fn peek(&self) -> Option<&T> {
+        self.get_position(0)
+    }
+
+    #[inline]
+    pub fn peek_id(&self) -> Option<(Id<Arena>, &T)> {
+        self.get_position_with_id(0)
+    }
+
+    #[inline]
+    pub fn get_position(&self, position: usize) -> Option<&T> {
+        self.inner.get_position(position).map(|value| &value.value)
+    }
+
+    #[inline]
+    pub fn get_position_with_id(&self, position: usize) -> Option<(Id<Arena>, &T)> {
+        self.inner
+            .get_position_with_id(position)
+            .map(|(id, value)| (Id::new(*id), &value.value))
+    }
+
+    #[inline]
+    pub fn pop(&mut self) -> Option<(Id<Arena>, T)> {
+        self.remove_position(0)
+    }
+
+    #[inline]
+    pub fn remove_position(&mut self, position: usize) -> Option<(Id<Arena>, T)> {
+        self.inner
+            .remove_position(position)
+            .map(|(id, value)| (Id::new(id), value.value))
+    }
+
+    /// As in [`UntypedIndexedQueue::try_remove_position`].
+    #[inline]
+    pub fn try_remove_position(&mut self, position: usize) -> Result<(Id<Arena>, T), QueueError> {
+        self.inner
+            .try_remove_position(position)
+            .map(|(id, value)| (Id::new(id), value.value))
+    }
+
+    /// Replaces the value for `id` if `value` is smaller than it, placing the entry behind the entries that share `value`.
+    #[inline]
+    pub fn decrease(&mut self, id: impl ValidId<Arena = Arena>, value: T) -> Update<T>
+    where
+        T: Ord,
+    {
+        let id = id.id().untyped;
+        match self.inner.get(id) {
+            Some(current) if value < current.value => self.resequence(id, value),
+            Some(_) => Update::NotImproved,
+            None => Update::Absent,
+        }
+    }
+
+    /// As in [`UntypedIndexedQueue::try_decrease`].
+    #[inline]
+    pub fn try_decrease(
+        &mut self,
+        id: impl ValidId<Arena = Arena>,
+        value: T,
+    ) -> Result<T, QueueError>
+    where
+        T: Ord,
+    {
+        self.decrease(id, value).into_result()
+    }
+
+    /// Replaces the value for `id` if `value` is larger than it, placing the entry behind the entries that share `value`.
+    #[inline]
+    pub fn increase(&mut self, id: impl ValidId<Arena = Arena>, value: T) -> Update<T>
+    where
+        T: Ord,
+    {
+        let id = id.id().untyped;
+        match self.inner.get(id) {
+            Some(current) if value > current.value => self.resequence(id, value),
+            Some(_) => Update::NotImproved,
+            None => Update::Absent,
+        }
+    }
+
+    /// Moves the entry for `id` behind the entries that share `value` if it is ordered before its current value.
+    #[inline]
+    pub fn promote(&mut self, id: impl ValidId<Arena = Arena>, value: T) -> Update<T> {
+        let id = id.id().untyped;
+        match self.compare_current(id, &value) {
+            Some(Ordering::Less) => self.resequence(id, value),
+            Some(_) => Update::NotImproved,
+            None => Update::Absent,
+        }
+    }
+
+    /// Moves the entry for `id` behind the entries that share `value` if it is ordered after its current value.
+    #[inline]
+    pub fn demote(&mut self, id: impl ValidId<Arena = Arena>, value: T) -> Update<T> {
+        let id = id.id().untyped;
+        match self.compare_current(id, &value) {
+            Some(Ordering::Greater) => self.resequence(id, value),
+            Some(_) => Update::NotImproved,
+            None => Update::Absent,
+        }
+    }
+
+    /// As in [`UntypedIndexedQueue::retain_live`].
+    #[inline]
+    pub fn retain_live(&mut self, allocator: &Allocator<Arena>) {
+        self.inner.retain(|id, _| allocator.is_alive(Id::new(id)));
+    }
+
+    /// As in [`UntypedIndexedQueue::retain`].
+    #[inline]
+    pub fn retain(&mut self, mut f: impl FnMut(Id<Arena>, &T) -> bool) {
+        self.inner.retain(|id, value| f(Id::new(id), &value.value));
+    }
+
+    /// As in [`UntypedIndexedQueue::iter_sorted`].
+    #[inline]
+    pub fn iter_sorted(&self) -> impl Iterator<Item = (Id<Arena>, &T)> {
+        self.inner
+            .iter_sorted()
+            .map(|(id, value)| (Id::new(*id), &value.value))
+    }
+
+    /// As in [`UntypedIndexedQueue::iter_unordered`].
+    #[inline]
+    pub fn iter_unordered(&self) -> impl Iterator<Item = (Id<Arena>, &T)> {
+        self.inner
+            .iter_unordered()
+            .map(|(id, value)| (Id::new(*id), &value.value))
+    }
+
+    /// As in [`UntypedIndexedQueue::drain_sorted`].
+    #[inline]
+    pub fn drain_sorted(&mut self) -> impl Iterator<Item = (Id<Arena>, T)> + '_ {
+        self.inner
+            .drain_sorted()
+            .map(|(id, value)| (Id::new(id), value.value))
+    }
+
+    /// As in [`UntypedIndexedQueue::drain`].
+    #[inline]
+    pub fn drain(&mut self) -> impl Iterator<Item = (Id<Arena>, T)> + '_ {
+        self.inner
+            .drain()
+            .map(|(id, value)| (Id::new(id), value.value))
+    }
+
+    #[inline]
+    pub fn into_sorted_vec(mut self) -> Vec<(Id<Arena>, T)> {
+        self.drain_sorted().collect()
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Checks the invariants of the underlying queue, as in [`UntypedIndexedQueue::validate`].
+    #[inline]
+    pub fn validate(&self) -> Result<(), InvariantError> {
+        self.inner.validate()
+    }
+
+    #[inline]
+    fn compare_current(&self, id: UntypedId, value: &T) -> Option<Ordering> {
+        let current = self.inner.get(id)?;
+        Some(self.comparator().compare(value, &current.value))
+    }
+
+    /// Replaces the value for `id` with `value` and a new sequence number.
+    #[inline]
+    fn resequence(&mut self, id: UntypedId, value: T) -> Update<T> {
+        let position = match self.inner.position(id) {
+            Some(position) => position,
+            None => return Update::Absent,
+        };
+
+        let value = self.sequenced(value);
+        match self.inner.replace_at(position, value) {
+            Some(old) => Update::Updated { old: old.value },
+            None => Update::Absent,
+        }
+    }
+
+    #[inline]
+    fn sequenced(&mut self, value: T) -> Sequenced<T> {
+        let sequence = self.sequence;
+        self.sequence += 1;
+        Sequenced { value, sequence }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::storage::CAPACITY;
+    use crate::test_util::{get_id, Arena};
+
+    fn pop_all<T: Ord>(queue: &mut StableIndexedMinQueue<Arena, T>) -> Vec<Id<Arena>> {
+        let len = queue.len();
+        (0..len)
+            .filter_map(|_| queue.pop())
+            .map(|(id, _)| id)
+            .collect()
+    }
+
+    #[test]
+    fn equal_priorities_pop_in_insertion_order() {
+        let mut queue = StableIndexedMinQueue::<Arena, u32>::default();
+
+        for i in 0..50 {
+            queue.insert(get_id(i), 1);
+        }
+
+        assert_eq!((0..50).map(get_id).collect::<Vec<_>>(), pop_all(&mut queue));
+    }
+
+    #[test]
+    fn re_insert_moves_to_back_of_equal_priorities() {
+        let mut queue = StableIndexedMinQueue::<Arena, u32>::default();
+
+        queue.insert(get_id(0), 1);
+        queue.insert(get_id(1), 1);
+        queue.insert(get_id(2), 1);
+        queue.insert(get_id(0), 1);
+
+        assert_eq!(vec![get_id(1), get_id(2), get_id(0)], pop_all(&mut queue));
+    }
+
+    #[test]
+    fn decrease_queues_behind_existing_priority() {
+        let mut queue = StableIndexedMinQueue::<Arena, u32>::default();
+
+        queue.insert(get_id(0), 3);
+        queue.insert(get_id(1), 1);
+        queue.insert(get_id(2), 1);
+        queue.decrease(get_id(0), 1);
+
+        assert_eq!(vec![get_id(1), get_id(2), get_id(0)], pop_all(&mut queue));
+    }
+
+    #[test]
+    fn increase_queues_behind_existing_priority() {
+        let mut queue = StableIndexedMinQueue::<Arena, u32>::default();
+
+        queue.insert(get_id(0), 2);
+        queue.insert(get_id(1), 1);
+        queue.insert(get_id(2), 2);
+        queue.increase(get_id(1), 2);
+
+        assert_eq!(vec![get_id(0), get_id(2), get_id(1)], pop_all(&mut queue));
+    }
+
+    #[test]
+    fn unchanged_priority_keeps_its_place() {
+        let mut queue = StableIndexedMinQueue::<Arena, u32>::default();
+
+        queue.insert(get_id(0), 1);
+        queue.insert(get_id(1), 1);
+        queue.decrease(get_id(0), 1);
+        queue.increase(get_id(0), 1);
+
+        assert_eq!(vec![get_id(0), get_id(1)], pop_all(&mut queue));
+    }
+
+    #[test]
+    fn max_queue_pops_equal_priorities_in_insertion_order() {
+        let mut queue = StableIndexedMaxQueue::<Arena, u32>::default();
+
+        queue.insert(get_id(0), 1);
+        queue.insert(get_id(1), 2);
+        queue.insert(get_id(2), 2);
+        queue.insert(get_id(3), 1);
+
+        let ids = (0..4)
+            .filter_map(|_| queue.pop())
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+
+        assert_eq!(vec![get_id(1), get_id(2), get_id(0), get_id(3)], ids);
+    }
+
+    #[test]
+    fn max_queue_decrease_lowers_value() {
+        let mut queue = StableIndexedMaxQueue::<Arena, u32>::default();
+        queue.insert(get_id(0), 3);
+
+        assert_eq!(Update::Updated { old: 3 }, queue.decrease(get_id(0), 1));
+    }
+
+    #[test]
+    fn into_sorted_vec_keeps_insertion_order() {
+        let mut queue = StableIndexedMinQueue::<Arena, u32>::default();
+
+        queue.insert(get_id(0), 2);
+        queue.insert(get_id(1), 1);
+        queue.insert(get_id(2), 2);
+
+        assert_eq!(
+            vec![(get_id(1), 1), (get_id(0), 2), (get_id(2), 2)],
+            queue.into_sorted_vec()
+        );
+    }
+
+    #[test]
+    fn failed_insert_keeps_fifo_order() {
+        let capacity = CAPACITY as usize;
+        let mut queue = StableIndexedMinQueue::<Arena, u32>::default();
+        queue.insert(get_id(0), 1);
+        for i in 1..capacity {
+            queue.insert(get_id(i), 2);
+        }
+        let sequence = queue.sequence;
+
+        assert_eq!(
+            Err(QueueError::CapacityOverflow),
+            queue.try_insert(get_id(capacity), 1)
+        );
+        assert_eq!(sequence, queue.sequence);
+
+        queue.remove(get_id(1));
+        queue.try_insert(get_id(capacity), 1).unwrap();
+
+        assert_eq!(Some((get_id(0), 1)), queue.pop());
+        assert_eq!(Some((get_id(capacity), 1)), queue.pop());
+    }
+}
@@ -0,0 +1,430 @@
+use crate::error::QueueError;
+use crate::invariant::{self, InvariantError};
+use gen_id_allocator::untyped::UntypedId;
+use gen_id_component::UntypedComponent;
+use std::ops::{Index, IndexMut};
+
+/// The number of positions a queue can address, since each one is stored as a `u32`.
+#[cfg(not(test))]
+const CAPACITY: u64 = 1 << 32;
+
+/// Tests use a small capacity so that it can be reached.
+#[cfg(test)]
+pub(crate) const CAPACITY: u64 = 1 << 10;
+
+/// The entries of an indexed heap and the maps between their ids and positions.
+///
+/// Positions are only ever moved by [`swap`](Self::swap), so each heap decides the order while
+/// this keeps the maps consistent.
+#[derive(Debug)]
+pub(crate) struct Storage<T> {
+    /// The values that are sorted by the queue
+    pub(crate) values: UntypedComponent<Option<T>>,
+    /// Map from Id to position in queue
+    pub(crate) position_map: UntypedComponent<Option<u32>>,
+    /// Map from position in queue to Id
+    pub(crate) inverse_map: Vec<UntypedId>,
+}
+
+impl<T> Default for Storage<T> {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            values: Default::default(),
+            position_map: Default::default(),
+            inverse_map: Default::default(),
+        }
+    }
+}
+
+impl<T: Clone> Clone for Storage<T> {
+    #[inline]
+    fn clone(&self) -> Self {
+        Self {
+            values: self.values.clone(),
+            position_map: self.position_map.clone(),
+            inverse_map: self.inverse_map.clone(),
+        }
+    }
+
+    #[inline]
+    fn clone_from(&mut self, rhs: &Self) {
+        self.values.clone_from(&rhs.values);
+        self.position_map.clone_from(&rhs.position_map);
+        self.inverse_map.clone_from(&rhs.inverse_map);
+    }
+}
+
+impl<T> Storage<T> {
+    /// Returns the position of `id`, or `None` if it is absent or its slot holds a different generation.
+    #[inline]
+    pub(crate) fn position(&self, id: UntypedId) -> Option<usize> {
+        self.slot_position(id)
+            .filter(|position| self.inverse_map.get(*position) == Some(&id))
+    }
+
+    /// Returns the position of the entry in the slot of `id`, whatever its generation.
+    #[inline]
+    pub(crate) fn slot_position(&self, id: UntypedId) -> Option<usize> {
+        self.position_map
+            .get(id)
+            .and_then(|position| position.map(|p| p as usize))
+    }
+
+    #[inline]
+    pub(crate) fn len(&self) -> usize {
+        self.inverse_map.len()
+    }
+
+    /// Returns true if another entry can be given a position.
+    #[inline]
+    pub(crate) fn has_room(&self) -> bool {
+        (self.len() as u64) < CAPACITY
+    }
+
+    #[inline]
+    pub(crate) fn get_position(&self, position: usize) -> Option<&T> {
+        let id = self.inverse_map.get(position)?;
+        self.values.index(id).as_ref()
+    }
+
+    #[inline]
+    pub(crate) fn get_position_with_id(&self, position: usize) -> Option<(&UntypedId, &T)> {
+        let id = self.inverse_map.get(position)?;
+        let value = self.values.index(id).as_ref()?;
+        Some((id, value))
+    }
+
+    #[inline]
+    pub(crate) fn get_position_mut(&mut self, position: usize) -> Option<&mut T> {
+        let id = *self.inverse_map.get(position)?;
+        self.values.index_mut(id).as_mut()
+    }
+
+    #[inline]
+    pub(crate) fn swap(&mut self, a: usize, b: usize) {
+        if let (Some(id_a), Some(id_b)) = (self.inverse_map.get(a), self.inverse_map.get(b)) {
+            self.position_map.swap(*id_a, *id_b);
+
+            self.inverse_map.swap(a, b);
+        }
+    }
+
+    /// Adds an entry at the back of the heap without restoring the heap order, returning its position.
+    ///
+    /// Panics if the queue cannot address another entry.
+    #[inline]
+    pub(crate) fn append(&mut self, id: UntypedId, value: T) -> usize {
+        assert!(self.has_room(), "queue positions must fit in a u32");
+
+        let index = self.inverse_map.len();
+        self.values.insert(id, Some(value));
+        self.position_map.insert(id, Some(index as u32));
+        self.inverse_map.push(id);
+        index
+    }
+
+    /// Removes the entry at `position` by moving the last entry into its place, without restoring the heap order.
+    #[inline]
+    pub(crate) fn swap_remove(&mut self, position: usize) -> Option<(UntypedId, T)> {
+        let last = self.inverse_map.len().checked_sub(1)?;
+
+        if position <= last {
+            self.swap(position, last);
+
+            let id = self.inverse_map.pop()?;
+
+            let value = self.values.index_mut(id).take();
+            self.position_map.index_mut(id).take();
+
+            Some((id, value?))
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    pub(crate) fn clear(&mut self) {
+        self.values.fill_with(|| None);
+        self.position_map.fill_with(|| None);
+        self.inverse_map.clear();
+    }
+
+    /// Removes the entries for which `f` returns false and renumbers the others, without restoring the heap order.
+    #[inline]
+    pub(crate) fn retain_mut(&mut self, mut f: impl FnMut(UntypedId, &mut T) -> bool) {
+        let values = &mut self.values;
+        let position_map = &mut self.position_map;
+
+        self.inverse_map.retain(|id| {
+            let keep = match values.index_mut(*id).as_mut() {
+                Some(value) => f(*id, value),
+                None => false,
+            };
+            if !keep {
+                values.index_mut(*id).take();
+                position_map.index_mut(*id).take();
+            }
+            keep
+        });
+
+        // Positions only shrink here, and each one fit in a u32 when its entry was appended
+        for (position, id) in self.inverse_map.iter().enumerate() {
+            *self.position_map.index_mut(*id) = Some(position as u32);
+        }
+    }
+
+    /// Iterates over the entries in heap order.
+    #[inline]
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&UntypedId, &T)> {
+        self.inverse_map
+            .iter()
+            .filter_map(move |id| Some((id, self.values.get(*id)?.as_ref()?)))
+    }
+
+    #[inline]
+    pub(crate) fn drain(&mut self) -> Drain<'_, T> {
+        // Leave the storage empty up front, so that leaking the iterator cannot leave stale positions behind
+        self.position_map.fill_with(|| None);
+
+        Drain {
+            ids: std::mem::take(&mut self.inverse_map).into_iter(),
+            values: std::mem::take(&mut self.values),
+            queue_values: &mut self.values,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn into_iter(self) -> IntoIter<T> {
+        IntoIter {
+            ids: self.inverse_map.into_iter(),
+            values: self.values,
+        }
+    }
+
+    /// Checks that the maps are inverses and that exactly the queued ids have values.
+    #[inline]
+    pub(crate) fn validate(&self) -> Result<(), InvariantError> {
+        invariant::validate_storage(&self.values, &self.position_map, &self.inverse_map)
+    }
+}
+
+/// A heap that orders the entries of a [`Storage`].
+///
+/// Each heap only decides where an entry belongs; adding, removing and rebuilding are shared here.
+pub(crate) trait Heap {
+    type Value;
+
+    fn storage(&self) -> &Storage<Self::Value>;
+
+    fn storage_mut(&mut self) -> &mut Storage<Self::Value>;
+
+    /// Restores the heap order around a value at `position` that may have moved in either direction.
+    fn fix(&mut self, position: usize);
+
+    /// Restores the heap order of every entry in O(n).
+    fn heapify(&mut self);
+
+    /// Validates the heap if the `debug_invariants` feature is enabled.
+    fn check_invariants(&self);
+
+    /// Returns true if inserting `id` would update its entry or the queue can address another one.
+    #[inline]
+    fn can_insert(&self, id: UntypedId) -> bool {
+        self.storage().position(id).is_some() || self.storage().has_room()
+    }
+
+    /// Adds an entry for an `id` that is not in the queue, evicting any stale entry in its slot.
+    ///
+    /// Panics if the queue cannot address another entry.
+    #[inline]
+    fn push(&mut self, id: UntypedId, value: Self::Value) {
+        if let Some(stale) = self.storage().slot_position(id) {
+            self.remove_position(stale);
+        }
+
+        let position = self.storage_mut().append(id, value);
+        self.fix(position);
+        self.check_invariants();
+    }
+
+    #[inline]
+    fn remove_position(&mut self, position: usize) -> Option<(UntypedId, Self::Value)> {
+        let removed = self.storage_mut().swap_remove(position)?;
+
+        if position < self.storage().len() {
+            self.fix(position);
+        }
+        self.check_invariants();
+
+        Some(removed)
+    }
+
+    /// Removes the entry at `position`, or reports whether the queue was empty or `position` was past its end.
+    #[inline]
+    fn try_remove_position(
+        &mut self,
+        position: usize,
+    ) -> Result<(UntypedId, Self::Value), QueueError> {
+        match self.storage().len() {
+            0 => Err(QueueError::Empty),
+            len if position >= len => Err(QueueError::OutOfBounds { position, len }),
+            _ => self.remove_position(position).ok_or(QueueError::Absent),
+        }
+    }
+
+    /// Removes the entries for which `f` returns false, restoring the heap order once in O(n).
+    #[inline]
+    fn retain_mut(&mut self, f: impl FnMut(UntypedId, &mut Self::Value) -> bool) {
+        self.storage_mut().retain_mut(f);
+        self.heapify();
+        self.check_invariants();
+    }
+
+    /// Adds every entry, calling `resolve` for ids that already have an entry. If an entry and the
+    /// queue hold different generations of a slot, only the newer generation is kept.
+    ///
+    /// Like `BinaryHeap::append`, each entry is moved into place as it arrives while the batch is
+    /// small next to the queue. Once rebuilding would be cheaper, the remaining entries are added
+    /// without restoring the heap order, which is then restored bottom-up once at the end.
+    #[inline]
+    fn extend_with(
+        &mut self,
+        iter: impl IntoIterator<Item = (UntypedId, Self::Value)>,
+        mut resolve: impl FnMut(Self::Value, Self::Value) -> Self::Value,
+    ) {
+        let mut added = 0;
+        let mut rebuild = false;
+
+        for (id, value) in iter {
+            added += 1;
+            rebuild = rebuild || is_cheaper_to_rebuild(self.storage().len(), added);
+
+            if let Some(position) = self.storage().position(id) {
+                let slot = self.storage_mut().values.index_mut(id);
+                if let Some(current) = slot.take() {
+                    *slot = Some(resolve(current, value));
+                    if !rebuild {
+                        self.fix(position);
+                    }
+                }
+                continue;
+            }
+
+            if let Some(stale) = self.storage().slot_position(id) {
+                // The slot holds another generation, and only the newer of the two can still be alive
+                if self.storage().inverse_map[stale].gen() > id.gen() {
+                    continue;
+                }
+                self.storage_mut().swap_remove(stale);
+                if !rebuild && stale < self.storage().len() {
+                    self.fix(stale);
+                }
+            }
+
+            let position = self.storage_mut().append(id, value);
+            if !rebuild {
+                self.fix(position);
+            }
+        }
+
+        if rebuild {
+            self.heapify();
+        }
+        self.check_invariants();
+    }
+}
+
+/// Returns true once adding `added` entries one at a time to a heap of `len` would cost more
+/// comparisons than rebuilding it, following the heuristic of `BinaryHeap::append`.
+#[inline]
+fn is_cheaper_to_rebuild(len: usize, added: usize) -> bool {
+    let depth = (usize::BITS - len.leading_zeros()) as usize;
+    added.saturating_mul(depth) > 2 * len
+}
+
+impl<T> Index<UntypedId> for Storage<T> {
+    type Output = Option<T>;
+
+    #[inline]
+    fn index(&self, index: UntypedId) -> &Self::Output {
+        match self.position(index) {
+            Some(_) => self.values.index(index),
+            None => &None,
+        }
+    }
+}
+
+/// A draining iterator over the entries of a queue in heap order.
+///
+/// Holds the values while draining and hands their storage back to the queue when dropped.
+#[derive(Debug)]
+pub struct Drain<'a, T> {
+    ids: std::vec::IntoIter<UntypedId>,
+    values: UntypedComponent<Option<T>>,
+    queue_values: &'a mut UntypedComponent<Option<T>>,
+}
+
+impl<'a, T> Iterator for Drain<'a, T> {
+    type Item = (UntypedId, T);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let id = self.ids.next()?;
+        let value = self.values.index_mut(id).take()?;
+        Some((id, value))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.ids.size_hint()
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Drain<'a, T> {}
+
+impl<'a, T> Drop for Drain<'a, T> {
+    #[inline]
+    fn drop(&mut self) {
+        self.for_each(drop);
+        std::mem::swap(self.queue_values, &mut self.values);
+    }
+}
+
+/// An owning iterator over the entries of a queue in heap order.
+#[derive(Debug)]
+pub struct IntoIter<T> {
+    ids: std::vec::IntoIter<UntypedId>,
+    values: UntypedComponent<Option<T>>,
+}
+
+impl<T> Iterator for IntoIter<T> {
+    type Item = (UntypedId, T);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let id = self.ids.next()?;
+        let value = self.values.index_mut(id).take()?;
+        Some((id, value))
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.ids.size_hint()
+    }
+}
+
+impl<T> ExactSizeIterator for IntoIter<T> {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn is_cheaper_to_rebuild_for_large_batches() {
+        assert!(!is_cheaper_to_rebuild(0, 1));
+        assert!(!is_cheaper_to_rebuild(1000, 1));
+        assert!(!is_cheaper_to_rebuild(1000, 100));
+        assert!(is_cheaper_to_rebuild(1000, 500));
+    }
+}
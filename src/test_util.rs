@@ -0,0 +1,11 @@
+use gen_id_allocator::untyped::UntypedId;
+use gen_id_allocator::Id;
+
+/// The arena shared by the typed queue tests.
+#[derive(Debug)]
+pub(crate) struct Arena;
+
+/// Returns the first generation of the id in slot `index`.
+pub(crate) fn get_id(index: usize) -> Id<Arena> {
+    Id::new(UntypedId::first(index))
+}
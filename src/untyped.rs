@@ -1,230 +1,506 @@
-use gen_id_allocator::untyped::UntypedId;
-use gen_id_component::UntypedComponent;
-use std::ops::{Index, IndexMut};
-
-const ARITY: usize = 8;
+use crate::compare::{Compare, Max, Min};
+use crate::error::QueueError;
+use crate::invariant::{self, InvariantError};
+use crate::storage::{Heap, Storage};
+use crate::update::Update;
+use gen_id_allocator::untyped::{UntypedAllocator, UntypedId};
+use std::cmp::Ordering;
+use std::iter::FromIterator;
+use std::ops::Index;
+
+pub use crate::storage::{Drain, IntoIter};
 
 /// An indexed min priority queue based on a D-ary heap.
+pub type UntypedIndexedMinQueue<T, const D: usize = 8> = UntypedIndexedQueue<T, Min, D>;
+
+/// An indexed max priority queue based on a D-ary heap.
+pub type UntypedIndexedMaxQueue<T, const D: usize = 8> = UntypedIndexedQueue<T, Max, D>;
+
+/// An indexed priority queue based on a D-ary heap, ordered by the comparator `C`.
 #[derive(Debug)]
-pub struct UntypedIndexedMinQueue<T> {
-    /// The values that are sorted by the queue
-    values: UntypedComponent<Option<T>>,
-    /// Map from Id to position in queue
-    position_map: UntypedComponent<Option<u32>>,
-    /// Map from position in queue to Id
-    inverse_map: Vec<UntypedId>,
+pub struct UntypedIndexedQueue<T, C = Min, const D: usize = 8> {
+    /// The values and the maps between their ids and positions
+    storage: Storage<T>,
+    /// Determines the order of the values
+    compare: C,
 }
 
-impl<T> Default for UntypedIndexedMinQueue<T> {
+impl<T, C: Default, const D: usize> Default for UntypedIndexedQueue<T, C, D> {
     #[inline]
     fn default() -> Self {
-        Self {
-            values: Default::default(),
-            position_map: Default::default(),
-            inverse_map: Default::default(),
-        }
+        Self::with_comparator(C::default())
     }
 }
 
-impl<T: Clone> Clone for UntypedIndexedMinQueue<T> {
+impl<T: Clone, C: Clone, const D: usize> Clone for UntypedIndexedQueue<T, C, D> {
     #[inline]
     fn clone(&self) -> Self {
         Self {
-            values: self.values.clone(),
-            position_map: self.position_map.clone(),
-            inverse_map: self.inverse_map.clone(),
+            storage: self.storage.clone(),
+            compare: self.compare.clone(),
         }
     }
 
     #[inline]
     fn clone_from(&mut self, rhs: &Self) {
-        self.values.clone_from(&rhs.values);
-        self.position_map.clone_from(&rhs.position_map);
-        self.inverse_map.clone_from(&rhs.inverse_map);
+        self.storage.clone_from(&rhs.storage);
+        self.compare.clone_from(&rhs.compare);
     }
 }
 
-impl<T: Ord + Copy> UntypedIndexedMinQueue<T> {
+impl<T, C, const D: usize> UntypedIndexedQueue<T, C, D> {
+    const VALID_ARITY: () = assert!(D >= 2, "a D-ary heap requires D >= 2");
+
     #[inline]
-    pub fn clear(&mut self) {
-        self.values.fill_with(|| None);
-        self.position_map.fill_with(|| None);
-        self.inverse_map.clear();
+    pub fn with_comparator(compare: C) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::VALID_ARITY;
+
+        Self {
+            storage: Default::default(),
+            compare,
+        }
     }
 
     #[inline]
-    pub fn insert(&mut self, id: UntypedId, value: T) {
-        self.values.insert(id, Some(value));
+    pub fn comparator(&self) -> &C {
+        &self.compare
+    }
 
-        if let Some(Some(index)) = self.position_map.get(id) {
-            let index = *index as usize;
-            self.sink(index);
-            self.swim(index);
-        } else {
-            let index = self.inverse_map.len();
-            self.position_map.insert(id, Some(index as u32));
-            self.inverse_map.push(id);
+    /// Returns the position of `id`, or `None` if it is absent or its slot holds a different generation.
+    #[inline]
+    pub fn position(&self, id: UntypedId) -> Option<usize> {
+        self.storage.position(id)
+    }
+}
 
-            self.swim(index);
-        }
+impl<T, C: Compare<T>, const D: usize> UntypedIndexedQueue<T, C, D> {
+    #[inline]
+    pub fn clear(&mut self) {
+        self.storage.clear();
+        self.check_invariants();
     }
 
+    /// Inserts or updates the entry for `id`.
+    ///
+    /// Panics if the queue cannot address another entry; see [`try_insert`](Self::try_insert).
     #[inline]
-    pub fn remove(&mut self, id: UntypedId) -> Option<(UntypedId, T)> {
-        let position = self
-            .position_map
-            .get(id)
-            .and_then(|i| i.as_ref())
-            .map(|u32| *u32 as usize)?;
+    pub fn insert(&mut self, id: UntypedId, value: T) {
+        match self.position(id) {
+            Some(position) => {
+                self.replace_at(position, value);
+            }
+            None => self.push(id, value),
+        }
+    }
 
-        let last = self.inverse_map.len() - 1;
+    /// Inserts or updates the entry for `id`, failing if the queue cannot address another entry.
+    #[inline]
+    pub fn try_insert(&mut self, id: UntypedId, value: T) -> Result<(), QueueError> {
+        if !self.can_insert(id) {
+            return Err(QueueError::CapacityOverflow);
+        }
 
-        self.swap(position, last);
+        self.insert(id, value);
+        Ok(())
+    }
 
-        let value = self.values.index_mut(id).take();
-        self.position_map.index_mut(id).take();
-        let id = self.inverse_map.pop();
+    /// Adds an entry for an `id` that is not in the queue, evicting any stale entry in its slot.
+    ///
+    /// Panics if the queue cannot address another entry.
+    #[inline]
+    pub fn push(&mut self, id: UntypedId, value: T) {
+        Heap::push(self, id, value);
+    }
 
-        self.sink(position);
-        self.swim(position);
+    #[inline]
+    pub fn remove(&mut self, id: UntypedId) -> Option<(UntypedId, T)> {
+        let position = self.position(id)?;
+        self.remove_position(position)
+    }
 
-        Some((id?, value?))
+    #[inline]
+    pub fn get(&self, id: UntypedId) -> Option<&T> {
+        self.get_position(self.position(id)?)
     }
 
     #[inline]
     pub fn get_position(&self, position: usize) -> Option<&T> {
-        let id = self.inverse_map.get(position)?;
-        self.values.index(id).as_ref()
+        self.storage.get_position(position)
     }
 
     #[inline]
     pub fn get_position_with_id(&self, position: usize) -> Option<(&UntypedId, &T)> {
-        let id = self.inverse_map.get(position)?;
-        let value = self.values.index(id).as_ref()?;
-        Some((id, value))
+        self.storage.get_position_with_id(position)
     }
 
     #[inline]
     pub fn remove_position(&mut self, position: usize) -> Option<(UntypedId, T)> {
-        let last = self.inverse_map.len() - 1;
+        Heap::remove_position(self, position)
+    }
 
-        if position <= last {
-            self.swap(position, last);
+    /// Removes the entry at `position`, or reports whether the queue was empty or `position` was past its end.
+    #[inline]
+    pub fn try_remove_position(&mut self, position: usize) -> Result<(UntypedId, T), QueueError> {
+        Heap::try_remove_position(self, position)
+    }
 
-            let id = self.inverse_map.pop()?;
+    /// Replaces the value for `id` if `value` is smaller than it, whichever way the comparator then moves the entry.
+    #[inline]
+    pub fn decrease(&mut self, id: UntypedId, value: T) -> Update<T>
+    where
+        T: Ord,
+    {
+        match self.position(id) {
+            Some(position) => self.decrease_at(position, value),
+            None => Update::Absent,
+        }
+    }
 
-            let value = self.values.index_mut(id).take();
-            self.position_map.index_mut(id).take();
+    /// Decreases the entry for `id` as in [`decrease`](Self::decrease), returning the old value.
+    #[inline]
+    pub fn try_decrease(&mut self, id: UntypedId, value: T) -> Result<T, QueueError>
+    where
+        T: Ord,
+    {
+        self.decrease(id, value).into_result()
+    }
 
-            self.sink(position);
-            self.swim(position);
+    /// Replaces the value for `id` if `value` is larger than it, whichever way the comparator then moves the entry.
+    #[inline]
+    pub fn increase(&mut self, id: UntypedId, value: T) -> Update<T>
+    where
+        T: Ord,
+    {
+        match self.position(id) {
+            Some(position) => self.increase_at(position, value),
+            None => Update::Absent,
+        }
+    }
 
-            Some((id, value?))
-        } else {
-            None
+    /// Moves the entry for `id` towards the front of the queue if `value` is ordered before its current value.
+    #[inline]
+    pub fn promote(&mut self, id: UntypedId, value: T) -> Update<T> {
+        match self.position(id) {
+            Some(position) => self.promote_at(position, value),
+            None => Update::Absent,
+        }
+    }
+
+    /// Moves the entry for `id` towards the back of the queue if `value` is ordered after its current value.
+    #[inline]
+    pub fn demote(&mut self, id: UntypedId, value: T) -> Update<T> {
+        match self.position(id) {
+            Some(position) => self.demote_at(position, value),
+            None => Update::Absent,
+        }
+    }
+
+    /// Moves every entry of `other` into the queue in O(n + m), leaving `other` empty.
+    ///
+    /// An id that is in both queues takes the value from `other`. If the two queues hold different
+    /// generations of a slot, the entry for the newer generation is kept.
+    #[inline]
+    pub fn append(&mut self, other: &mut Self) {
+        self.extend(other.drain());
+    }
+
+    /// Moves every entry of `other` into the queue in O(n + m).
+    ///
+    /// An id that is in both queues takes the value returned by `resolve(current, other)`. If the
+    /// two queues hold different generations of a slot, the entry for the newer generation is kept
+    /// without calling `resolve`.
+    #[inline]
+    pub fn merge_with(&mut self, other: Self, resolve: impl FnMut(T, T) -> T) {
+        self.extend_with(other, resolve);
+    }
+
+    /// Builds a queue from entries in any order and restores the heap order in O(n).
+    ///
+    /// Fails with the offending id if two entries share a slot.
+    #[inline]
+    pub(crate) fn try_from_entries(
+        entries: impl IntoIterator<Item = (UntypedId, T)>,
+        compare: C,
+    ) -> Result<Self, UntypedId> {
+        let mut queue = Self::with_comparator(compare);
+
+        for (id, value) in entries {
+            if queue.storage.slot_position(id).is_some() {
+                return Err(id);
+            }
+            queue.storage.append(id, value);
+        }
+
+        queue.heapify();
+        queue.check_invariants();
+
+        Ok(queue)
+    }
+
+    /// Removes the entries whose ids are no longer alive in `allocator`.
+    #[inline]
+    pub fn retain_live(&mut self, allocator: &UntypedAllocator) {
+        self.retain(|id, _| allocator.is_alive(id));
+    }
+
+    /// Removes the entries for which `f` returns false, restoring the heap order once in O(n).
+    #[inline]
+    pub fn retain(&mut self, mut f: impl FnMut(UntypedId, &T) -> bool) {
+        self.retain_mut(|id, value| f(id, value));
+    }
+
+    /// Removes the entries for which `f` returns false and keeps any changes `f` makes to the
+    /// others, restoring the heap order once in O(n).
+    #[inline]
+    pub fn retain_mut(&mut self, f: impl FnMut(UntypedId, &mut T) -> bool) {
+        Heap::retain_mut(self, f);
+    }
+
+    /// Inserts the entry if `id` is absent, otherwise decreases it. Returns true if the queue changed.
+    #[inline]
+    pub fn push_or_decrease(&mut self, id: UntypedId, value: T) -> bool
+    where
+        T: Ord,
+    {
+        match self.position(id) {
+            Some(position) => self.decrease_at(position, value).is_updated(),
+            None => {
+                self.push(id, value);
+                true
+            }
         }
     }
 
+    /// Inserts the entry if `id` is absent, otherwise promotes it. Returns true if the queue changed.
     #[inline]
-    pub fn decrease(&mut self, id: UntypedId, value: T) {
-        if let (Some(current_value), Some(index)) =
-            (self.values.index_mut(id), self.position_map.index(id))
-        {
-            if value < *current_value {
-                *current_value = value;
-                let index = *index as usize;
-                self.swim(index);
+    pub fn push_or_promote(&mut self, id: UntypedId, value: T) -> bool {
+        match self.position(id) {
+            Some(position) => self.promote_at(position, value).is_updated(),
+            None => {
+                self.push(id, value);
+                true
             }
         }
     }
 
+    /// Replaces the value at `position`, returning the previous value.
     #[inline]
-    pub fn increase(&mut self, id: UntypedId, value: T) {
-        if let (Some(current_value), Some(index)) =
-            (self.values.index_mut(id), self.position_map.index(id))
-        {
-            if value > *current_value {
-                *current_value = value;
-                let index = *index as usize;
-                self.sink(index);
+    pub fn replace_at(&mut self, position: usize, value: T) -> Option<T> {
+        let current = self.storage.get_position_mut(position)?;
+
+        let ordering = self.compare.compare(&value, current);
+        let previous = std::mem::replace(current, value);
+
+        match ordering {
+            Ordering::Less => {
+                self.swim(position);
             }
+            Ordering::Greater => self.sink(position),
+            Ordering::Equal => {}
         }
+        self.check_invariants();
+
+        Some(previous)
     }
 
+    /// Replaces the value at `position` if `value` is smaller than it.
     #[inline]
-    fn sink(&mut self, mut index: usize) -> Option<()> {
-        while let Some(child) = self.min_child(index) {
-            let child_value = self.get_position(child)?;
-            let parent_value = self.get_position(index)?;
+    pub fn decrease_at(&mut self, position: usize, value: T) -> Update<T>
+    where
+        T: Ord,
+    {
+        self.replace_if(position, value, |value, current| value < current)
+    }
+
+    /// Replaces the value at `position` if `value` is larger than it.
+    #[inline]
+    pub fn increase_at(&mut self, position: usize, value: T) -> Update<T>
+    where
+        T: Ord,
+    {
+        self.replace_if(position, value, |value, current| value > current)
+    }
+
+    /// Replaces the value at `position` if `accept(value, current)` returns true.
+    #[inline]
+    fn replace_if(
+        &mut self,
+        position: usize,
+        value: T,
+        accept: impl FnOnce(&T, &T) -> bool,
+    ) -> Update<T> {
+        let accepted = match self.get_position(position) {
+            Some(current) => accept(&value, current),
+            None => return Update::Absent,
+        };
+
+        if !accepted {
+            return Update::NotImproved;
+        }
+
+        match self.replace_at(position, value) {
+            Some(old) => Update::Updated { old },
+            None => Update::Absent,
+        }
+    }
+
+    /// Replaces the value at `position` if `value` is ordered before it.
+    #[inline]
+    pub fn promote_at(&mut self, position: usize, value: T) -> Update<T> {
+        let current = self.storage.get_position_mut(position);
+
+        match current {
+            Some(current) if self.compare.compare(&value, current) == Ordering::Less => {
+                let old = std::mem::replace(current, value);
+                self.swim(position);
+                self.check_invariants();
+                Update::Updated { old }
+            }
+            Some(_) => Update::NotImproved,
+            None => Update::Absent,
+        }
+    }
+
+    /// Replaces the value at `position` if `value` is ordered after it.
+    #[inline]
+    pub fn demote_at(&mut self, position: usize, value: T) -> Update<T> {
+        let current = self.storage.get_position_mut(position);
+
+        match current {
+            Some(current) if self.compare.compare(&value, current) == Ordering::Greater => {
+                let old = std::mem::replace(current, value);
+                self.sink(position);
+                self.check_invariants();
+                Update::Updated { old }
+            }
+            Some(_) => Update::NotImproved,
+            None => Update::Absent,
+        }
+    }
+
+    /// Modifies the value at `position` in place and restores the heap order.
+    #[inline]
+    pub fn update_at(&mut self, position: usize, f: impl FnOnce(&mut T)) {
+        if let Some(current) = self.storage.get_position_mut(position) {
+            f(current);
+            self.fix(position);
+            self.check_invariants();
+        }
+    }
 
-            if child_value < parent_value {
-                self.swap(index, child);
+    #[inline]
+    fn sink(&mut self, mut index: usize) {
+        while let Some(child) = self.min_child(index) {
+            if self.is_less(child, index) {
+                self.storage.swap(index, child);
                 index = child;
             } else {
-                return Some(());
+                return;
             }
         }
-        Some(())
     }
 
     #[inline]
     fn min_child(&self, parent: usize) -> Option<usize> {
         self.get_children(parent)
-            .filter_map(|child| {
-                self.inverse_map
-                    .get(child)
-                    .and_then(|id| self.values.get(*id).map(|value| (child, value)))
-            })
-            .reduce(|(min, min_value), (next, next_value)| {
-                if next_value < min_value {
-                    (next, next_value)
-                } else {
-                    (min, min_value)
-                }
-            })
-            .map(|(min, _value)| min)
+            .reduce(|min, next| if self.is_less(next, min) { next } else { min })
     }
 
+    /// Moves the value at `index` towards the front of the queue, returning its final position.
     #[inline]
-    fn swim(&mut self, mut index: usize) -> Option<()> {
-        while let Some(parent) = get_parent(index, ARITY) {
-            let parent_value = self.get_position(parent)?;
-            let child_value = self.get_position(index)?;
-
-            if child_value < parent_value {
-                self.swap(index, parent);
+    fn swim(&mut self, mut index: usize) -> usize {
+        while let Some(parent) = get_parent(index, D) {
+            if self.is_less(index, parent) {
+                self.storage.swap(index, parent);
                 index = parent;
             } else {
-                return Some(());
+                break;
             }
         }
-        Some(())
+        index
     }
 
+    /// Returns true if the value at position `a` is ordered before the value at position `b`.
     #[inline]
-    fn swap(&mut self, a: usize, b: usize) {
-        if let (Some(id_a), Some(id_b)) = (self.inverse_map.get(a), self.inverse_map.get(b)) {
-            self.position_map.swap(*id_a, *id_b);
-
-            self.inverse_map.swap(a, b);
+    fn is_less(&self, a: usize, b: usize) -> bool {
+        match (self.get_position(a), self.get_position(b)) {
+            (Some(a), Some(b)) => self.compare.compare(a, b) == Ordering::Less,
+            _ => false,
         }
     }
 
     #[inline]
     fn get_children(&self, index: usize) -> std::ops::Range<usize> {
-        get_children(index, self.inverse_map.len(), ARITY)
+        get_children(index, self.len(), D)
     }
 
+    /// Iterates over the entries in priority order without modifying the queue.
+    ///
+    /// Yielding `k` entries takes O(k·D·log(k·D)).
     #[inline]
-    pub fn iter_sorted(&self) -> impl Iterator<Item = (&UntypedId, &T)> {
-        self.inverse_map
-            .iter()
-            .map(move |id| (id, self.values.index(id).as_ref().unwrap()))
+    pub fn iter_sorted(&self) -> IterSorted<'_, T, C, D> {
+        IterSorted::new(self)
+    }
+
+    /// Iterates over the entries in heap order. Only the first entry is guaranteed to be at the front of the queue.
+    #[inline]
+    pub fn iter_unordered(&self) -> impl Iterator<Item = (&UntypedId, &T)> {
+        self.storage.iter()
+    }
+
+    /// Lazily pops entries from the front of the queue while `f` returns true for their value.
+    #[inline]
+    pub fn pop_while<'a>(
+        &'a mut self,
+        mut f: impl FnMut(&T) -> bool + 'a,
+    ) -> impl Iterator<Item = (UntypedId, T)> + 'a {
+        PopWhile {
+            queue: self,
+            f: move |_: &C, value: &T| f(value),
+        }
+    }
+
+    /// Lazily pops the entries that are ordered at or before `threshold`.
+    #[inline]
+    pub fn pop_until(&mut self, threshold: T) -> impl Iterator<Item = (UntypedId, T)> + '_ {
+        PopWhile {
+            queue: self,
+            f: move |compare: &C, value: &T| {
+                compare.compare(value, &threshold) != Ordering::Greater
+            },
+        }
+    }
+
+    /// Iterates over the entries in priority order while `f` returns true for their value, without modifying the queue.
+    #[inline]
+    pub fn peek_while<'a>(
+        &'a self,
+        mut f: impl FnMut(&T) -> bool + 'a,
+    ) -> impl Iterator<Item = (&'a UntypedId, &'a T)> + 'a {
+        self.iter_sorted().take_while(move |(_, value)| f(value))
+    }
+
+    /// Removes the entries in priority order. Any entries left when the iterator is dropped are removed.
+    #[inline]
+    pub fn drain_sorted(&mut self) -> DrainSorted<'_, T, C, D> {
+        DrainSorted { queue: self }
+    }
+
+    /// Removes the entries in heap order in O(n). Any entries left when the iterator is dropped are removed.
+    #[inline]
+    pub fn drain(&mut self) -> Drain<'_, T> {
+        self.storage.drain()
+    }
+
+    #[inline]
+    pub fn into_sorted_vec(mut self) -> Vec<(UntypedId, T)> {
+        self.drain_sorted().collect()
     }
 
     #[inline]
     pub fn len(&self) -> usize {
-        self.inverse_map.len()
+        self.storage.len()
     }
 
     #[inline]
@@ -232,45 +508,263 @@ impl<T: Ord + Copy> UntypedIndexedMinQueue<T> {
         self.len() == 0
     }
 
-    #[cfg(test)]
-    pub(crate) fn is_sorted(&self) -> bool {
-        self.inverse_map
-            .iter()
-            .enumerate()
-            .flat_map(|(i, id)| {
-                let parent_value = self.values.get(*id).unwrap();
-                self.get_children(i)
-                    .filter_map(|child_index| self.inverse_map.get(child_index))
-                    .map(|child_id| self.values.get(*child_id).unwrap())
-                    .map(move |child_value| (parent_value, child_value))
-            })
-            .all(|(parent, child)| child > parent)
+    /// Checks the heap order and the mapping between ids and positions in O(n), returning the
+    /// first broken invariant. Entries that compare equal may appear in any order.
+    #[inline]
+    pub fn validate(&self) -> Result<(), InvariantError> {
+        self.storage.validate()?;
+
+        for parent in 0..self.len() {
+            for child in self.get_children(parent) {
+                if self.is_less(child, parent) {
+                    return Err(InvariantError::HeapOrder { parent, child });
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<T, C: Compare<T>, const D: usize> Heap for UntypedIndexedQueue<T, C, D> {
+    type Value = T;
+
+    #[inline]
+    fn storage(&self) -> &Storage<T> {
+        &self.storage
+    }
+
+    #[inline]
+    fn storage_mut(&mut self) -> &mut Storage<T> {
+        &mut self.storage
+    }
+
+    #[inline]
+    fn fix(&mut self, position: usize) {
+        if self.swim(position) == position {
+            self.sink(position);
+        }
+    }
+
+    /// Sinks each parent, starting from the last.
+    #[inline]
+    fn heapify(&mut self) {
+        let last_parent = self
+            .len()
+            .checked_sub(1)
+            .and_then(|last| get_parent(last, D));
+
+        if let Some(last_parent) = last_parent {
+            for position in (0..=last_parent).rev() {
+                self.sink(position);
+            }
+        }
+    }
+
+    #[inline]
+    fn check_invariants(&self) {
+        invariant::debug_check(|| self.validate());
     }
 }
 
-impl<T> Index<UntypedId> for UntypedIndexedMinQueue<T> {
+impl<T, C, const D: usize> Index<UntypedId> for UntypedIndexedQueue<T, C, D> {
     type Output = Option<T>;
 
     #[inline]
     fn index(&self, index: UntypedId) -> &Self::Output {
-        self.values.index(index)
+        &self.storage[index]
+    }
+}
+
+/// Adds every entry in O(min(m log(n + m), n + m)), moving each into place while the batch is
+/// small next to the queue and otherwise restoring the heap order bottom-up once.
+///
+/// An id that is already in the queue, or repeated within the iterator, takes the last value given
+/// for it. An entry for an older generation of a queued slot is dropped.
+impl<T, C: Compare<T>, const D: usize> Extend<(UntypedId, T)> for UntypedIndexedQueue<T, C, D> {
+    #[inline]
+    fn extend<I: IntoIterator<Item = (UntypedId, T)>>(&mut self, iter: I) {
+        self.extend_with(iter, |_, value| value);
+    }
+}
+
+impl<T, C: Compare<T> + Default, const D: usize> FromIterator<(UntypedId, T)>
+    for UntypedIndexedQueue<T, C, D>
+{
+    #[inline]
+    fn from_iter<I: IntoIterator<Item = (UntypedId, T)>>(iter: I) -> Self {
+        let mut queue = Self::default();
+        queue.extend(iter);
+        queue
+    }
+}
+
+/// Yields the entries in heap order.
+impl<T, C, const D: usize> IntoIterator for UntypedIndexedQueue<T, C, D> {
+    type Item = (UntypedId, T);
+    type IntoIter = IntoIter<T>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.storage.into_iter()
     }
 }
 
-impl<'a, T> IntoIterator for &'a UntypedIndexedMinQueue<T> {
+impl<'a, T, C, const D: usize> IntoIterator for &'a UntypedIndexedQueue<T, C, D> {
     type Item = &'a Option<T>;
     type IntoIter = std::slice::Iter<'a, Option<T>>;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.values.into_iter()
+        self.storage.values.into_iter()
+    }
+}
+
+/// An iterator over the entries of a queue in priority order.
+///
+/// Keeps a binary heap of the positions that may hold the next entry. Yielding an entry
+/// replaces its position with those of its children, so the queue itself is never modified.
+#[derive(Debug)]
+pub struct IterSorted<'a, T, C, const D: usize> {
+    queue: &'a UntypedIndexedQueue<T, C, D>,
+    frontier: Vec<usize>,
+}
+
+impl<'a, T, C: Compare<T>, const D: usize> IterSorted<'a, T, C, D> {
+    #[inline]
+    fn new(queue: &'a UntypedIndexedQueue<T, C, D>) -> Self {
+        let mut frontier = Vec::with_capacity(D);
+        if !queue.is_empty() {
+            frontier.push(0);
+        }
+        Self { queue, frontier }
+    }
+
+    #[inline]
+    fn is_less(&self, a: usize, b: usize) -> bool {
+        self.queue.is_less(self.frontier[a], self.frontier[b])
+    }
+
+    #[inline]
+    fn push(&mut self, position: usize) {
+        self.frontier.push(position);
+
+        let mut index = self.frontier.len() - 1;
+        while let Some(parent) = get_parent(index, 2) {
+            if self.is_less(index, parent) {
+                self.frontier.swap(index, parent);
+                index = parent;
+            } else {
+                break;
+            }
+        }
+    }
+
+    #[inline]
+    fn pop(&mut self) -> Option<usize> {
+        let last = self.frontier.len().checked_sub(1)?;
+        self.frontier.swap(0, last);
+        let position = self.frontier.pop();
+
+        let mut index = 0;
+        while let Some(child) = self.min_child(index) {
+            if self.is_less(child, index) {
+                self.frontier.swap(index, child);
+                index = child;
+            } else {
+                break;
+            }
+        }
+
+        position
+    }
+
+    #[inline]
+    fn min_child(&self, parent: usize) -> Option<usize> {
+        get_children(parent, self.frontier.len(), 2).reduce(|min, next| {
+            if self.is_less(next, min) {
+                next
+            } else {
+                min
+            }
+        })
+    }
+}
+
+impl<'a, T, C: Compare<T>, const D: usize> Iterator for IterSorted<'a, T, C, D> {
+    type Item = (&'a UntypedId, &'a T);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let position = self.pop()?;
+
+        for child in self.queue.get_children(position) {
+            self.push(child);
+        }
+
+        self.queue.get_position_with_id(position)
+    }
+}
+
+/// Pops entries from the front of a queue while `f` accepts them.
+struct PopWhile<'a, T, C, F, const D: usize> {
+    queue: &'a mut UntypedIndexedQueue<T, C, D>,
+    f: F,
+}
+
+impl<'a, T, C: Compare<T>, F: FnMut(&C, &T) -> bool, const D: usize> Iterator
+    for PopWhile<'a, T, C, F, D>
+{
+    type Item = (UntypedId, T);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        let front = self.queue.get_position(0)?;
+        if (self.f)(&self.queue.compare, front) {
+            self.queue.remove_position(0)
+        } else {
+            None
+        }
+    }
+}
+
+/// A draining iterator over the entries of a queue in priority order.
+#[derive(Debug)]
+pub struct DrainSorted<'a, T, C: Compare<T>, const D: usize> {
+    queue: &'a mut UntypedIndexedQueue<T, C, D>,
+}
+
+impl<'a, T, C: Compare<T>, const D: usize> Iterator for DrainSorted<'a, T, C, D> {
+    type Item = (UntypedId, T);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.queue.is_empty() {
+            return None;
+        }
+        self.queue.remove_position(0)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.queue.len();
+        (len, Some(len))
+    }
+}
+
+impl<'a, T, C: Compare<T>, const D: usize> ExactSizeIterator for DrainSorted<'a, T, C, D> {}
+
+impl<'a, T, C: Compare<T>, const D: usize> Drop for DrainSorted<'a, T, C, D> {
+    #[inline]
+    fn drop(&mut self) {
+        self.queue.clear();
     }
 }
 
-fn get_parent(index: usize, arity: usize) -> Option<usize> {
+pub(crate) fn get_parent(index: usize, arity: usize) -> Option<usize> {
     index.checked_sub(1).map(|i| i / arity)
 }
 
-fn get_children(index: usize, len: usize, arity: usize) -> std::ops::Range<usize> {
+pub(crate) fn get_children(index: usize, len: usize, arity: usize) -> std::ops::Range<usize> {
     let i = index * arity;
     let min = i + 1;
     let max = (i + arity + 1).min(len);
@@ -280,10 +774,12 @@ fn get_children(index: usize, len: usize, arity: usize) -> std::ops::Range<usize
 #[cfg(test)]
 mod test {
     use super::*;
-    use gen_id_allocator::untyped::UntypedAllocator;
+    use crate::compare::by_key;
+    use crate::storage::CAPACITY;
     use rand::distributions::{Distribution, Standard};
-    use rand::prelude::IteratorRandom;
+    use rand::prelude::{IteratorRandom, SliceRandom};
     use rand::{thread_rng, Rng};
+    use std::ops::IndexMut;
 
     #[test]
     fn parent_child() {
@@ -293,7 +789,7 @@ mod test {
         assert!(get_children(1, 10, 4).any(|c| c == 7));
     }
 
-    fn new_queue() -> UntypedIndexedMinQueue<u32> {
+    fn new_queue<const D: usize>() -> UntypedIndexedQueue<u32, Min, D> {
         Default::default()
     }
 
@@ -301,126 +797,119 @@ mod test {
         UntypedId::first(index)
     }
 
-    #[test]
-    fn insert_out_of_order() {
-        let mut queue = new_queue();
+    fn insert_out_of_order<const D: usize>() {
+        let mut queue = new_queue::<D>();
 
         queue.insert(get_id(0), 3);
-        assert!(queue.is_sorted());
+        queue.validate().unwrap();
 
         queue.insert(get_id(1), 2);
-        assert!(queue.is_sorted());
+        queue.validate().unwrap();
 
-        assert_eq!(vec![get_id(1), get_id(0)], queue.inverse_map);
+        assert_eq!(vec![get_id(1), get_id(0)], queue.storage.inverse_map);
     }
 
-    #[test]
-    fn insert_in_order() {
-        let mut queue = new_queue();
+    fn insert_in_order<const D: usize>() {
+        let mut queue = new_queue::<D>();
 
         queue.insert(get_id(0), 3);
-        assert!(queue.is_sorted());
+        queue.validate().unwrap();
 
         queue.insert(get_id(1), 4);
-        assert!(queue.is_sorted());
+        queue.validate().unwrap();
 
-        assert_eq!(vec![get_id(0), get_id(1)], queue.inverse_map);
+        assert_eq!(vec![get_id(0), get_id(1)], queue.storage.inverse_map);
     }
 
-    #[test]
-    fn re_insert() {
-        let mut queue = new_queue();
+    fn re_insert<const D: usize>() {
+        let mut queue = new_queue::<D>();
 
         queue.insert(get_id(0), 3);
-        assert!(queue.is_sorted());
+        queue.validate().unwrap();
 
         queue.insert(get_id(1), 2);
-        assert!(queue.is_sorted());
+        queue.validate().unwrap();
 
         queue.insert(get_id(1), 4);
-        assert!(queue.is_sorted());
+        queue.validate().unwrap();
     }
 
-    #[test]
-    fn remove_from_empty_returns_none() {
-        let mut queue = new_queue();
+    fn remove_from_empty_returns_none<const D: usize>() {
+        let mut queue = new_queue::<D>();
 
         assert_eq!(None, queue.remove(get_id(0)));
+        assert_eq!(None, queue.remove_position(0));
     }
 
-    #[test]
-    fn remove_from_3() {
-        let mut queue = new_queue();
+    fn remove_from_3<const D: usize>() {
+        let mut queue = new_queue::<D>();
 
         queue.insert(get_id(0), 1);
-        assert!(queue.is_sorted());
+        queue.validate().unwrap();
 
         queue.insert(get_id(1), 2);
-        assert!(queue.is_sorted());
+        queue.validate().unwrap();
 
         queue.insert(get_id(2), 3);
-        assert!(queue.is_sorted());
+        queue.validate().unwrap();
 
         queue.remove(get_id(1));
-        assert!(queue.is_sorted());
+        queue.validate().unwrap();
 
-        assert_eq!(vec![get_id(0), get_id(2)], queue.inverse_map);
+        assert_eq!(vec![get_id(0), get_id(2)], queue.storage.inverse_map);
     }
 
-    #[test]
-    fn remove_from_4() {
-        let mut queue = new_queue();
+    fn remove_from_4<const D: usize>() {
+        let mut queue = new_queue::<D>();
 
         queue.insert(get_id(0), 1);
-        assert!(queue.is_sorted());
+        queue.validate().unwrap();
 
         queue.insert(get_id(1), 2);
-        assert!(queue.is_sorted());
+        queue.validate().unwrap();
 
         queue.insert(get_id(2), 3);
-        assert!(queue.is_sorted());
+        queue.validate().unwrap();
 
         queue.insert(get_id(3), 4);
-        assert!(queue.is_sorted());
+        queue.validate().unwrap();
 
         queue.remove(get_id(1));
-        assert!(queue.is_sorted());
+        queue.validate().unwrap();
     }
 
-    #[test]
-    fn insert_after_remove() {
-        let mut queue = new_queue();
+    fn insert_after_remove<const D: usize>() {
+        let mut queue = new_queue::<D>();
 
         queue.insert(get_id(0), 0);
-        assert!(queue.is_sorted());
+        queue.validate().unwrap();
 
         queue.insert(get_id(1), 1);
-        assert!(queue.is_sorted());
+        queue.validate().unwrap();
 
         queue.insert(get_id(2), 2);
-        assert!(queue.is_sorted());
+        queue.validate().unwrap();
 
         queue.remove(get_id(1));
-        assert!(queue.is_sorted());
+        queue.validate().unwrap();
 
         queue.insert(get_id(1), 3);
-        assert!(queue.is_sorted());
+        queue.validate().unwrap();
     }
 
-    #[test]
-    fn pop() {
-        let mut queue = new_queue();
+    fn pop<const D: usize>() {
+        let mut queue = new_queue::<D>();
 
         queue.insert(get_id(0), 0);
 
         queue.insert(get_id(1), 1);
 
-        assert_eq!(queue.inverse_map.get(0).unwrap(), &get_id(0));
+        assert_eq!(queue.storage.inverse_map.first().unwrap(), &get_id(0));
 
         let (id, value) = queue.remove_position(0).unwrap();
         assert_eq!(id, get_id(0));
         assert_eq!(value, 0);
-        assert!(queue.is_sorted());
+        queue.validate().unwrap();
     }
 
     fn get_random_id<R: Rng>(alloc: &UntypedAllocator, rng: &mut R) -> Option<UntypedId> {
@@ -446,17 +935,16 @@ mod test {
         }
     }
 
-    #[test]
-    fn procedural_test() {
+    fn procedural_test<const D: usize>() {
         let rng = &mut thread_rng();
         let mut alloc = UntypedAllocator::default();
-        let mut queue = new_queue();
+        let mut queue = new_queue::<D>();
         let mut empty: Vec<UntypedId> = vec![];
 
         for _ in 0..10 {
             let id = alloc.create();
             queue.insert(id, rng.gen());
-            assert!(queue.is_sorted());
+            queue.validate().unwrap();
         }
 
         for _ in 0..1000 {
@@ -464,31 +952,31 @@ mod test {
                 Action::InsertNew => {
                     let id = alloc.create();
                     queue.insert(id, rng.gen());
-                    assert!(queue.is_sorted());
+                    queue.validate().unwrap();
                 }
                 Action::Update => {
                     if let Some(id) = get_random_id(&alloc, rng) {
                         queue.insert(id, rng.gen());
-                        assert!(queue.is_sorted());
+                        queue.validate().unwrap();
                     }
                 }
                 Action::Remove => {
                     if let Some(id) = get_random_id(&alloc, rng) {
                         empty.push(id);
                         queue.remove(id);
-                        assert!(queue.is_sorted());
+                        queue.validate().unwrap();
                     }
                 }
                 Action::Reuse => {
                     if !empty.is_empty() {
                         let id = empty.swap_remove(rng.gen_range(0, empty.len()));
                         queue.insert(id, rng.gen());
-                        assert!(queue.is_sorted());
+                        queue.validate().unwrap();
                     }
                 }
             }
         }
-        assert!(queue.is_sorted());
+        queue.validate().unwrap();
     }
 
     #[test]
@@ -509,27 +997,812 @@ mod test {
         assert_eq!(vec![1, 2], get_children(0, 10, 2).collect::<Vec<_>>());
     }
 
-    #[test]
-    fn decrease() {
-        let mut queue = new_queue();
+    fn decrease<const D: usize>() {
+        let mut queue = new_queue::<D>();
 
         queue.insert(get_id(0), 3);
         queue.insert(get_id(1), 2);
         queue.decrease(get_id(0), 1);
 
-        assert!(queue.is_sorted());
-        assert_eq!(vec![get_id(0), get_id(1)], queue.inverse_map);
+        queue.validate().unwrap();
+        assert_eq!(vec![get_id(0), get_id(1)], queue.storage.inverse_map);
     }
 
-    #[test]
-    fn decrease_given_larger_value() {
-        let mut queue = new_queue();
+    fn iter_sorted_yields_priority_order<const D: usize>() {
+        let rng = &mut thread_rng();
+        let mut queue = new_queue::<D>();
+
+        for i in 0..100 {
+            queue.insert(get_id(i), rng.gen_range(0, 20));
+        }
+
+        let mut expected = queue.iter_unordered().map(|(_, v)| *v).collect::<Vec<_>>();
+        expected.sort_unstable();
+
+        let actual = queue.iter_sorted().map(|(_, v)| *v).collect::<Vec<_>>();
+
+        assert_eq!(expected, actual);
+        assert_eq!(100, queue.len());
+    }
+
+    fn iter_sorted_given_empty_queue<const D: usize>() {
+        let queue = new_queue::<D>();
+
+        assert_eq!(None, queue.iter_sorted().next());
+    }
+
+    fn decrease_given_larger_value<const D: usize>() {
+        let mut queue = new_queue::<D>();
 
         queue.insert(get_id(0), 3);
         queue.insert(get_id(1), 2);
         queue.decrease(get_id(0), 4);
 
-        assert!(queue.is_sorted());
-        assert_eq!(vec![get_id(1), get_id(0)], queue.inverse_map);
+        queue.validate().unwrap();
+        assert_eq!(vec![get_id(1), get_id(0)], queue.storage.inverse_map);
+    }
+
+    fn from_iter_heapifies<const D: usize>() {
+        let mut values = (0..100).collect::<Vec<u32>>();
+        values.shuffle(&mut thread_rng());
+
+        let queue = values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| (get_id(i), *value))
+            .collect::<UntypedIndexedQueue<u32, Min, D>>();
+
+        queue.validate().unwrap();
+        assert_eq!(100, queue.len());
+        for (i, value) in values.iter().enumerate() {
+            assert_eq!(Some(value), queue.get(get_id(i)));
+        }
+    }
+
+    fn extend_replaces_existing_values<const D: usize>() {
+        let mut queue = new_queue::<D>();
+
+        queue.insert(get_id(0), 3);
+        queue.insert(get_id(1), 2);
+        queue.extend(vec![(get_id(1), 5), (get_id(2), 4), (get_id(2), 1)]);
+
+        queue.validate().unwrap();
+        assert_eq!(3, queue.len());
+        assert_eq!(Some(&5), queue.get(get_id(1)));
+        assert_eq!(Some((get_id(2), 1)), queue.remove_position(0));
+    }
+
+    fn drain_sorted_yields_priority_order<const D: usize>() {
+        let mut values = (0..50).collect::<Vec<u32>>();
+        values.shuffle(&mut thread_rng());
+        let mut queue = values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| (get_id(i), *value))
+            .collect::<UntypedIndexedQueue<u32, Min, D>>();
+
+        let drained = queue
+            .drain_sorted()
+            .map(|(_, value)| value)
+            .collect::<Vec<_>>();
+
+        assert_eq!((0..50).collect::<Vec<_>>(), drained);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn drain_sorted_dropped_early_empties_queue() {
+        let mut queue = new_queue::<8>();
+        queue.extend((0..10).map(|i| (get_id(i), i as u32)));
+
+        assert_eq!(Some((get_id(0), 0)), queue.drain_sorted().next());
+
+        assert!(queue.is_empty());
+        assert_eq!(None, queue.get(get_id(5)));
+        queue.insert(get_id(5), 1);
+        assert_eq!(Some((get_id(5), 1)), queue.remove_position(0));
+    }
+
+    #[test]
+    fn drain_dropped_early_empties_queue() {
+        let mut queue = new_queue::<8>();
+        queue.extend((0..10).map(|i| (get_id(i), i as u32)));
+
+        assert_eq!(Some((get_id(0), 0)), queue.drain().next());
+        assert!(queue.is_empty());
+
+        queue.extend((0..10).map(|i| (get_id(i), i as u32)));
+        let mut drained = queue.drain().collect::<Vec<_>>();
+        drained.sort();
+
+        assert!(queue.is_empty());
+        assert_eq!(None, queue.position(get_id(3)));
+        assert_eq!(
+            (0..10).map(|i| (get_id(i), i as u32)).collect::<Vec<_>>(),
+            drained
+        );
+    }
+
+    #[test]
+    fn leaked_drain_leaves_an_empty_queue() {
+        let mut queue = new_queue::<8>();
+        queue.extend((0..10).map(|i| (get_id(i), i as u32)));
+
+        std::mem::forget(queue.drain());
+
+        assert!(queue.is_empty());
+        assert_eq!(Ok(()), queue.validate());
+
+        queue.insert(get_id(3), 1);
+        queue.insert(get_id(0), 5);
+
+        assert_eq!(2, queue.len());
+        assert_eq!(Some(&1), queue.get(get_id(3)));
+        assert_eq!(Some(&5), queue.get(get_id(0)));
+        assert_eq!(Ok(()), queue.validate());
+    }
+
+    #[test]
+    fn into_iter_and_into_sorted_vec() {
+        let mut queue = new_queue::<8>();
+        queue.insert(get_id(0), 3);
+        queue.insert(get_id(1), 1);
+        queue.insert(get_id(2), 2);
+
+        let mut entries = queue.clone().into_iter().collect::<Vec<_>>();
+        entries.sort();
+
+        assert_eq!(
+            vec![(get_id(0), 3), (get_id(1), 1), (get_id(2), 2)],
+            entries
+        );
+        assert_eq!(
+            vec![(get_id(1), 1), (get_id(2), 2), (get_id(0), 3)],
+            queue.into_sorted_vec()
+        );
+    }
+
+    fn shard<const D: usize>(range: std::ops::Range<usize>) -> UntypedIndexedQueue<u32, Min, D> {
+        range.map(|i| (get_id(i), i as u32 * 10)).collect()
+    }
+
+    fn append_empties_other<const D: usize>() {
+        let mut queue = shard::<D>(0..20);
+        let mut other = shard::<D>(10..30);
+        other.insert(get_id(15), 1);
+
+        queue.append(&mut other);
+
+        queue.validate().unwrap();
+        assert!(other.is_empty());
+        assert_eq!(None, other.get(get_id(15)));
+        assert_eq!(30, queue.len());
+        assert_eq!(Some(&1), queue.get(get_id(15)));
+    }
+
+    fn merge_with_resolves_conflicts<const D: usize>() {
+        let mut other = shard::<D>(10..30);
+        other.insert(get_id(5), 1);
+        other.insert(get_id(6), 100);
+
+        let mut keep_min = shard::<D>(0..20);
+        keep_min.merge_with(other.clone(), std::cmp::min);
+        keep_min.validate().unwrap();
+        assert_eq!(30, keep_min.len());
+        assert_eq!(Some(&1), keep_min.get(get_id(5)));
+        assert_eq!(Some(&60), keep_min.get(get_id(6)));
+
+        let mut keep_max = shard::<D>(0..20);
+        keep_max.merge_with(other.clone(), std::cmp::max);
+        keep_max.validate().unwrap();
+        assert_eq!(Some(&50), keep_max.get(get_id(5)));
+        assert_eq!(Some(&100), keep_max.get(get_id(6)));
+
+        let mut keep_left = shard::<D>(0..20);
+        keep_left.merge_with(other, |current, _| current);
+        keep_left.validate().unwrap();
+        assert_eq!(Some(&50), keep_left.get(get_id(5)));
+        assert_eq!(Some(&60), keep_left.get(get_id(6)));
+    }
+
+    fn merge_keeps_the_newer_generation<const D: usize>() {
+        let mut queue = new_queue::<D>();
+        queue.insert(UntypedId::new(0, 1), 5);
+        queue.insert(UntypedId::new(1, 0), 6);
+
+        let mut other = new_queue::<D>();
+        other.insert(UntypedId::new(0, 0), 1);
+        other.insert(UntypedId::new(1, 1), 2);
+
+        let mut merged = queue.clone();
+        merged.merge_with(other.clone(), |_, _| unreachable!());
+        assert_eq!(Ok(()), merged.validate());
+        assert_eq!(2, merged.len());
+        assert_eq!(Some(&5), merged.get(UntypedId::new(0, 1)));
+        assert_eq!(Some(&2), merged.get(UntypedId::new(1, 1)));
+
+        queue.append(&mut other);
+        assert_eq!(Ok(()), queue.validate());
+        assert_eq!(
+            vec![(UntypedId::new(1, 1), 2), (UntypedId::new(0, 1), 5)],
+            queue.into_sorted_vec()
+        );
+    }
+
+    fn retain_removes_rejected_entries<const D: usize>() {
+        let mut queue = shard::<D>(0..50);
+
+        queue.retain(|id, _| id.index() % 3 != 0);
+
+        queue.validate().unwrap();
+        assert_eq!(33, queue.len());
+        for i in 0..50 {
+            assert_eq!(i % 3 != 0, queue.get(get_id(i)).is_some());
+        }
+        for (position, id) in queue.storage.inverse_map.iter().enumerate() {
+            assert_eq!(Some(position), queue.position(*id));
+        }
+    }
+
+    fn retain_mut_restores_order<const D: usize>() {
+        let mut queue = shard::<D>(0..50);
+
+        queue.retain_mut(|id, value| {
+            *value = 1000 - *value;
+            id.index() < 40
+        });
+
+        queue.validate().unwrap();
+        assert_eq!(40, queue.len());
+        assert_eq!(Some((get_id(39), 610)), queue.remove_position(0));
+    }
+
+    // Validating after every mutation would dominate the comparison count
+    #[cfg(not(feature = "debug_invariants"))]
+    #[test]
+    fn extend_with_a_few_entries_does_not_rebuild() {
+        let comparisons = std::cell::Cell::new(0);
+        let mut queue = UntypedIndexedQueue::<u32, _>::with_comparator(|a: &u32, b: &u32| {
+            comparisons.set(comparisons.get() + 1);
+            a.cmp(b)
+        });
+        queue.extend((0..1000).map(|i| (get_id(i), i as u32 * 2)));
+        queue.validate().unwrap();
+
+        comparisons.set(0);
+        queue.extend(vec![(get_id(1000), 1), (get_id(500), 3)]);
+
+        assert!(comparisons.get() < 100, "{}", comparisons.get());
+        queue.validate().unwrap();
+        assert_eq!(Some((get_id(0), 0)), queue.remove_position(0));
+        assert_eq!(Some((get_id(1000), 1)), queue.remove_position(0));
+        assert_eq!(Some((get_id(1), 2)), queue.remove_position(0));
+        assert_eq!(Some((get_id(500), 3)), queue.remove_position(0));
+    }
+
+    #[test]
+    fn extend_evicts_stale_entries() {
+        let mut alloc = UntypedAllocator::default();
+        let mut queue = new_queue::<8>();
+
+        let stale = alloc.create();
+        queue.insert(stale, 3);
+        alloc.kill(stale);
+        let id = alloc.create();
+        queue.extend(vec![(id, 4), (alloc.create(), 5)]);
+
+        assert_eq!(2, queue.len());
+        assert_eq!(None, queue.get(stale));
+        assert_eq!(Some((id, 4)), queue.remove_position(0));
+    }
+
+    #[test]
+    fn non_copy_priorities() {
+        let mut queue = UntypedIndexedQueue::<String>::default();
+
+        queue.insert(get_id(0), "c".to_string());
+        queue.insert(get_id(1), "b".to_string());
+        queue.insert(get_id(2), "d".to_string());
+        queue.decrease(get_id(2), "a".to_string());
+        queue.increase(get_id(1), "e".to_string());
+
+        assert_eq!(Some(&"a".to_string()), queue.get_position(0));
+        assert_eq!(Some((get_id(2), "a".to_string())), queue.remove_position(0));
+        assert_eq!(Some((get_id(1), "e".to_string())), queue.remove(get_id(1)));
+        assert_eq!(Some((get_id(0), "c".to_string())), queue.remove_position(0));
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn max_comparator() {
+        let mut queue = UntypedIndexedQueue::<u32, Max>::default();
+
+        queue.insert(get_id(0), 1);
+        queue.insert(get_id(1), 3);
+        queue.insert(get_id(2), 2);
+        queue.promote(get_id(0), 4);
+        queue.demote(get_id(1), 0);
+
+        queue.validate().unwrap();
+        assert_eq!(
+            vec![4, 2, 0],
+            queue.iter_sorted().map(|(_, v)| *v).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn max_queue_decrease_lowers_value() {
+        let mut queue = UntypedIndexedQueue::<u32, Max>::default();
+
+        queue.insert(get_id(0), 3);
+        queue.insert(get_id(1), 2);
+
+        assert_eq!(Update::NotImproved, queue.decrease(get_id(0), 4));
+        assert_eq!(Update::Updated { old: 3 }, queue.decrease(get_id(0), 1));
+        assert_eq!(Some(&2), queue.get_position(0));
+        assert_eq!(Update::Updated { old: 1 }, queue.increase(get_id(0), 5));
+        assert_eq!(Some(&5), queue.get_position(0));
+        queue.validate().unwrap();
+    }
+
+    #[test]
+    fn promote_and_demote_follow_the_comparator() {
+        let mut queue = UntypedIndexedQueue::<u32, Max>::default();
+
+        queue.insert(get_id(0), 3);
+
+        assert_eq!(Update::NotImproved, queue.promote(get_id(0), 2));
+        assert_eq!(Update::Updated { old: 3 }, queue.promote(get_id(0), 4));
+        assert_eq!(Update::NotImproved, queue.demote(get_id(0), 5));
+        assert_eq!(Update::Updated { old: 4 }, queue.demote(get_id(0), 1));
+        assert!(!queue.push_or_promote(get_id(0), 0));
+        assert!(queue.push_or_promote(get_id(0), 2));
+        assert!(queue.push_or_promote(get_id(1), 0));
+        assert_eq!(Some(&2), queue.get_position(0));
+    }
+
+    #[test]
+    fn closure_comparator() {
+        let mut queue: UntypedIndexedQueue<f64, _> =
+            UntypedIndexedQueue::with_comparator(|a: &f64, b: &f64| b.partial_cmp(a).unwrap());
+
+        queue.insert(get_id(0), 0.5);
+        queue.insert(get_id(1), 1.5);
+        queue.insert(get_id(2), -1.0);
+
+        queue.validate().unwrap();
+        assert_eq!(Some((get_id(1), 1.5)), queue.remove_position(0));
+    }
+
+    #[test]
+    fn by_key_comparator() {
+        let mut queue: UntypedIndexedQueue<(&str, u32), _> =
+            UntypedIndexedQueue::with_comparator(by_key(|(_, key): &(&str, u32)| *key));
+
+        queue.insert(get_id(0), ("a", 3));
+        queue.insert(get_id(1), ("b", 1));
+        queue.insert(get_id(2), ("c", 2));
+        queue.decrease(get_id(0), ("a", 0));
+
+        assert_eq!(
+            vec!["a", "b", "c"],
+            queue.iter_sorted().map(|(_, v)| v.0).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn try_remove_position_reports_errors() {
+        let mut queue = new_queue::<8>();
+        assert_eq!(Err(QueueError::Empty), queue.try_remove_position(0));
+
+        queue.try_insert(get_id(0), 3).unwrap();
+        queue.try_insert(get_id(1), 2).unwrap();
+
+        assert_eq!(
+            Err(QueueError::OutOfBounds {
+                position: 2,
+                len: 2
+            }),
+            queue.try_remove_position(2)
+        );
+        assert_eq!(Ok((get_id(1), 2)), queue.try_remove_position(0));
+    }
+
+    #[test]
+    fn try_insert_reports_capacity_overflow() {
+        let capacity = CAPACITY as usize;
+        let mut queue = (0..capacity)
+            .map(|i| (get_id(i), i as u32))
+            .collect::<UntypedIndexedMinQueue<u32>>();
+
+        assert_eq!(
+            Err(QueueError::CapacityOverflow),
+            queue.try_insert(get_id(capacity), 0)
+        );
+        assert_eq!(Ok(()), queue.try_insert(get_id(1), 0));
+        assert_eq!(capacity, queue.len());
+        assert_eq!(Some(&0), queue.get(get_id(1)));
+    }
+
+    #[test]
+    fn try_decrease_reports_errors() {
+        let mut queue = new_queue::<8>();
+        queue.insert(get_id(0), 3);
+
+        assert_eq!(
+            Err(QueueError::NotImproved),
+            queue.try_decrease(get_id(0), 3)
+        );
+        assert_eq!(Err(QueueError::Absent), queue.try_decrease(get_id(1), 1));
+        assert_eq!(Ok(3), queue.try_decrease(get_id(0), 2));
+        assert_eq!(Some(&2), queue.get(get_id(0)));
+    }
+
+    #[test]
+    fn decrease_reports_outcome() {
+        let mut queue = new_queue::<8>();
+
+        queue.insert(get_id(0), 3);
+
+        assert_eq!(Update::NotImproved, queue.decrease(get_id(0), 4));
+        assert_eq!(Update::NotImproved, queue.decrease(get_id(0), 3));
+        assert_eq!(Update::Updated { old: 3 }, queue.decrease(get_id(0), 2));
+        assert_eq!(Update::Absent, queue.decrease(get_id(1), 1));
+        assert_eq!(Some(&2), queue.get(get_id(0)));
+    }
+
+    #[test]
+    fn increase_reports_outcome() {
+        let mut queue = new_queue::<8>();
+
+        queue.insert(get_id(0), 3);
+
+        assert_eq!(Update::NotImproved, queue.increase(get_id(0), 2));
+        assert_eq!(Update::Updated { old: 3 }, queue.increase(get_id(0), 4));
+        assert_eq!(Update::Absent, queue.increase(get_id(1), 5));
+        assert_eq!(Some(&4), queue.get(get_id(0)));
+    }
+
+    #[test]
+    fn push_or_decrease() {
+        let mut queue = new_queue::<8>();
+
+        assert!(queue.push_or_decrease(get_id(0), 3));
+        assert!(!queue.push_or_decrease(get_id(0), 4));
+        assert!(queue.push_or_decrease(get_id(0), 2));
+        assert!(queue.push_or_decrease(get_id(1), 1));
+
+        assert_eq!(Some((get_id(1), 1)), queue.remove_position(0));
+        assert_eq!(Some((get_id(0), 2)), queue.remove_position(0));
+    }
+
+    #[test]
+    fn stale_id_is_absent() {
+        let mut alloc = UntypedAllocator::default();
+        let mut queue = new_queue::<8>();
+
+        let stale = alloc.create();
+        queue.insert(stale, 3);
+        alloc.kill(stale);
+        let id = alloc.create();
+
+        assert_eq!(stale.index(), id.index());
+        assert_eq!(None, queue.position(id));
+        assert_eq!(None, queue.get(id));
+        assert_eq!(&None, &queue[id]);
+        assert_eq!(Update::Absent, queue.decrease(id, 1));
+        assert_eq!(None, queue.remove(id));
+        assert_eq!(Some(&3), queue.get(stale));
+    }
+
+    #[test]
+    fn insert_evicts_stale_entry() {
+        let mut alloc = UntypedAllocator::default();
+        let mut queue = new_queue::<8>();
+
+        let stale = alloc.create();
+        queue.insert(stale, 3);
+        queue.insert(alloc.create(), 4);
+        alloc.kill(stale);
+        let id = alloc.create();
+        queue.insert(id, 5);
+
+        assert_eq!(2, queue.len());
+        assert_eq!(None, queue.get(stale));
+        assert_eq!(Some(&5), queue.get(id));
+        queue.validate().unwrap();
+    }
+
+    #[test]
+    fn retain_live() {
+        let mut alloc = UntypedAllocator::default();
+        let mut queue = new_queue::<8>();
+
+        let ids = (0..10).map(|_| alloc.create()).collect::<Vec<_>>();
+        for (value, id) in ids.iter().enumerate() {
+            queue.insert(*id, value as u32);
+        }
+        for id in ids.iter().step_by(2) {
+            alloc.kill(*id);
+        }
+
+        queue.retain_live(&alloc);
+
+        assert_eq!(5, queue.len());
+        queue.validate().unwrap();
+        assert!(queue.iter_unordered().all(|(id, _)| alloc.is_alive(*id)));
+    }
+
+    fn validate_accepts_duplicate_priorities<const D: usize>() {
+        let mut queue = new_queue::<D>();
+        for i in 0..40 {
+            queue.insert(get_id(i), (i % 3) as u32);
+            assert_eq!(Ok(()), queue.validate());
+        }
+
+        queue.decrease(get_id(7), 0);
+        queue.increase(get_id(3), 2);
+        queue.remove(get_id(12));
+        queue.remove_position(0);
+
+        assert_eq!(Ok(()), queue.validate());
+    }
+
+    fn three_entries() -> UntypedIndexedQueue<u32> {
+        (0..3).map(|i| (get_id(i), i as u32)).collect()
+    }
+
+    #[test]
+    fn validate_detects_heap_order() {
+        let mut queue = three_entries();
+        queue.storage.values.swap(get_id(0), get_id(1));
+
+        assert_eq!(
+            Err(InvariantError::HeapOrder {
+                parent: 0,
+                child: 1
+            }),
+            queue.validate()
+        );
+    }
+
+    #[test]
+    fn validate_detects_position_mismatch() {
+        let mut queue = three_entries();
+        queue.storage.inverse_map.swap(0, 1);
+
+        assert_eq!(
+            Err(InvariantError::PositionMismatch {
+                position: 0,
+                id: get_id(1),
+                mapped: Some(1),
+            }),
+            queue.validate()
+        );
+    }
+
+    #[test]
+    fn validate_detects_missing_value() {
+        let mut queue = three_entries();
+        queue.storage.values.index_mut(get_id(2)).take();
+
+        assert_eq!(
+            Err(InvariantError::MissingValue { id: get_id(2) }),
+            queue.validate()
+        );
+    }
+
+    #[test]
+    fn validate_detects_dangling_position() {
+        let mut queue = three_entries();
+        queue.storage.position_map.insert(get_id(5), Some(1));
+
+        assert_eq!(
+            Err(InvariantError::DanglingPosition {
+                slot: 5,
+                position: 1
+            }),
+            queue.validate()
+        );
+    }
+
+    #[test]
+    fn validate_detects_orphaned_value() {
+        let mut queue = three_entries();
+        queue.storage.values.insert(get_id(5), Some(1));
+
+        assert_eq!(
+            Err(InvariantError::OrphanedValue { slot: 5 }),
+            queue.validate()
+        );
+    }
+
+    macro_rules! test_arities {
+        ($($test:ident),* $(,)?) => {
+            mod arity {
+                $(
+                    #[test]
+                    fn $test() {
+                        super::$test::<2>();
+                        super::$test::<3>();
+                        super::$test::<4>();
+                        super::$test::<8>();
+                        super::$test::<16>();
+                    }
+                )*
+            }
+        };
+    }
+
+    test_arities!(
+        insert_out_of_order,
+        insert_in_order,
+        re_insert,
+        remove_from_empty_returns_none,
+        remove_from_3,
+        remove_from_4,
+        insert_after_remove,
+        pop,
+        procedural_test,
+        decrease,
+        iter_sorted_yields_priority_order,
+        iter_sorted_given_empty_queue,
+        decrease_given_larger_value,
+        from_iter_heapifies,
+        extend_replaces_existing_values,
+        drain_sorted_yields_priority_order,
+        append_empties_other,
+        merge_with_resolves_conflicts,
+        merge_keeps_the_newer_generation,
+        retain_removes_rejected_entries,
+        retain_mut_restores_order,
+        validate_accepts_duplicate_priorities,
+    );
+
+    /// Compares the queue against a `BTreeMap` from slot to `(generation, priority)`, using few
+    /// slots and priorities so that stale ids and ties are common.
+    mod model {
+        use super::*;
+        use proptest::collection::vec;
+        use proptest::prelude::*;
+        use std::collections::BTreeMap;
+
+        type Model = BTreeMap<usize, (u32, u32)>;
+
+        #[derive(Debug, Clone)]
+        enum Op {
+            Insert(UntypedId, u32),
+            Update(UntypedId, u32),
+            Remove(UntypedId),
+            Pop,
+            Decrease(UntypedId, u32),
+            Increase(UntypedId, u32),
+            Clear,
+        }
+
+        fn id() -> impl Strategy<Value = UntypedId> {
+            (0..6usize, 0..2u32).prop_map(|(index, gen)| UntypedId::new(index, gen))
+        }
+
+        fn priority() -> impl Strategy<Value = u32> {
+            0..4u32
+        }
+
+        fn op() -> impl Strategy<Value = Op> {
+            prop_oneof![
+                4 => (id(), priority()).prop_map(|(id, p)| Op::Insert(id, p)),
+                2 => (id(), priority()).prop_map(|(id, p)| Op::Update(id, p)),
+                2 => id().prop_map(Op::Remove),
+                2 => Just(Op::Pop),
+                2 => (id(), priority()).prop_map(|(id, p)| Op::Decrease(id, p)),
+                2 => (id(), priority()).prop_map(|(id, p)| Op::Increase(id, p)),
+                1 => Just(Op::Clear),
+            ]
+        }
+
+        fn model_get(model: &Model, id: UntypedId) -> Option<u32> {
+            match model.get(&id.index()) {
+                Some((gen, priority)) if *gen == id.gen() => Some(*priority),
+                _ => None,
+            }
+        }
+
+        fn model_update(
+            model: &mut Model,
+            id: UntypedId,
+            value: u32,
+            direction: Ordering,
+        ) -> Update<u32> {
+            match model_get(model, id) {
+                Some(old) if value.cmp(&old) == direction => {
+                    model.insert(id.index(), (id.gen(), value));
+                    Update::Updated { old }
+                }
+                Some(_) => Update::NotImproved,
+                None => Update::Absent,
+            }
+        }
+
+        fn apply<const D: usize>(
+            queue: &mut UntypedIndexedQueue<u32, Min, D>,
+            model: &mut Model,
+            op: &Op,
+        ) {
+            match *op {
+                Op::Insert(id, value) => {
+                    queue.insert(id, value);
+                    model.insert(id.index(), (id.gen(), value));
+                }
+                Op::Update(id, value) => {
+                    if let Some(position) = queue.position(id) {
+                        queue.update_at(position, |current| *current = value);
+                    }
+                    if model_get(model, id).is_some() {
+                        model.insert(id.index(), (id.gen(), value));
+                    }
+                }
+                Op::Remove(id) => {
+                    let expected = model_get(model, id).map(|value| (id, value));
+                    if expected.is_some() {
+                        model.remove(&id.index());
+                    }
+                    assert_eq!(expected, queue.remove(id));
+                }
+                Op::Pop => match queue.remove_position(0) {
+                    Some((id, value)) => {
+                        assert_eq!(model.values().map(|(_, p)| *p).min(), Some(value));
+                        assert_eq!(Some((id.gen(), value)), model.remove(&id.index()));
+                    }
+                    None => assert!(model.is_empty()),
+                },
+                Op::Decrease(id, value) => {
+                    let expected = model_update(model, id, value, Ordering::Less);
+                    assert_eq!(expected, queue.decrease(id, value));
+                }
+                Op::Increase(id, value) => {
+                    let expected = model_update(model, id, value, Ordering::Greater);
+                    assert_eq!(expected, queue.increase(id, value));
+                }
+                Op::Clear => {
+                    queue.clear();
+                    model.clear();
+                }
+            }
+        }
+
+        fn check<const D: usize>(ops: &[Op]) {
+            let mut queue = new_queue::<D>();
+            let mut model = Model::new();
+
+            for op in ops {
+                apply(&mut queue, &mut model, op);
+
+                assert_eq!(Ok(()), queue.validate());
+                assert_eq!(model.len(), queue.len());
+                assert_eq!(
+                    model.values().map(|(_, p)| *p).min(),
+                    queue.get_position(0).copied()
+                );
+                for (index, (gen, priority)) in &model {
+                    let id = UntypedId::new(*index, *gen);
+                    assert_eq!(Some(priority), queue.get(id));
+                    assert_eq!(None, queue.get(UntypedId::new(*index, 1 - *gen)));
+                }
+            }
+
+            let sorted = queue.into_sorted_vec();
+            let mut expected = model.values().map(|(_, p)| *p).collect::<Vec<_>>();
+            expected.sort_unstable();
+            assert_eq!(expected, sorted.iter().map(|(_, p)| *p).collect::<Vec<_>>());
+        }
+
+        proptest! {
+            #[test]
+            fn matches_model(ops in vec(op(), 1..200)) {
+                check::<2>(&ops);
+                check::<3>(&ops);
+                check::<8>(&ops);
+            }
+        }
     }
 }
@@ -0,0 +1,46 @@
+use crate::error::QueueError;
+
+/// The outcome of a `decrease`, `increase`, `promote` or `demote`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Update<T> {
+    /// The value was replaced and the entry moved within the queue.
+    Updated { old: T },
+    /// The new value would not have moved the entry in the requested direction, so the queue is unchanged.
+    NotImproved,
+    /// The id has no entry in the queue.
+    Absent,
+}
+
+impl<T> Update<T> {
+    #[inline]
+    pub fn is_updated(&self) -> bool {
+        matches!(self, Update::Updated { .. })
+    }
+
+    #[inline]
+    pub fn old(self) -> Option<T> {
+        match self {
+            Update::Updated { old } => Some(old),
+            _ => None,
+        }
+    }
+
+    /// Converts an update into the old value, or the error describing why the queue is unchanged.
+    #[inline]
+    pub fn into_result(self) -> Result<T, QueueError> {
+        match self {
+            Update::Updated { old } => Ok(old),
+            Update::NotImproved => Err(QueueError::NotImproved),
+            Update::Absent => Err(QueueError::Absent),
+        }
+    }
+
+    #[inline]
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Update<U> {
+        match self {
+            Update::Updated { old } => Update::Updated { old: f(old) },
+            Update::NotImproved => Update::NotImproved,
+            Update::Absent => Update::Absent,
+        }
+    }
+}